@@ -1,18 +1,270 @@
-use crate::values::Value::{self, *};
+use num_bigint::{BigInt, Sign};
+use num_integer::Integer as _;
+use num_rational::BigRational;
+use num_traits::{Pow, ToPrimitive};
+use std::cmp::Ordering;
+use std::f64;
 use std::ops;
 
+use crate::values::Value::{self, *};
+
 // because math is hard
+//
+// the numeric tower, from narrowest to widest, is
+//   Integer -> Bignum -> Rational -> Float
+// and every operation widens both sides to the wider of the two operands.
+// exact results are always shrunk back down to the narrowest type that can
+// hold them, so a `Bignum` never fits in an i64 and a `Rational` never has a
+// denominator of 1.
+
+impl Value {
+    /// wrap a `BigInt` in a `Value`, shrinking it back down to an `Integer` if it fits
+    pub fn from_bigint(n: BigInt) -> Value {
+        match n.to_i64() {
+            Some(n) => Integer(n),
+            None    => Bignum(n),
+        }
+    }
+
+    /// wrap a `BigRational` in a `Value`, shrinking it down to an integer if it is one
+    pub fn from_rational(n: BigRational) -> Value {
+        if n.is_integer() {
+            Value::from_bigint(n.to_integer())
+        } else {
+            Rational(n)
+        }
+    }
+
+    /// is this value an `Integer`, `Bignum`, `Rational` or `Float`?
+    pub fn is_number(&self) -> bool {
+        matches!(self, Integer(_) | Bignum(_) | Rational(_) | Float(_))
+    }
+
+    /// is this value an exact number (anything but a `Float`)?
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Integer(_) | Bignum(_) | Rational(_))
+    }
+
+    /// is this value an integer, exact or not?
+    pub fn is_integer(&self) -> bool {
+        match self {
+            Integer(_) | Bignum(_) => true,
+            Float(n) => n.is_finite() && n.fract() == 0.0,
+            _ => false,
+        }
+    }
+
+    /// widen an integral value to a `BigInt`
+    pub fn to_bigint(&self) -> BigInt {
+        match self {
+            Integer(n) => BigInt::from(*n),
+            Bignum(n)  => n.clone(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// widen an exact value to a `BigRational`
+    pub fn to_rational(&self) -> BigRational {
+        match self {
+            Rational(n) => n.clone(),
+            _ => BigRational::from_integer(self.to_bigint()),
+        }
+    }
+
+    /// convert a numeric value to a float, saturating to ±INF if it's too big
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Integer(n)  => *n as f64,
+            Float(n)    => *n,
+            Bignum(n)   => bigint_to_f64(n),
+            Rational(n) => bigint_to_f64(n.numer()) / bigint_to_f64(n.denom()),
+            _ => unreachable!(),
+        }
+    }
+
+    /// compare two numbers, whatever their types
+    pub fn num_cmp(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Integer(a), Integer(b)) => a.partial_cmp(b),
+            (Float(_), _) | (_, Float(_)) => self.to_f64().partial_cmp(&other.to_f64()),
+            (Rational(_), _) | (_, Rational(_)) => self.to_rational().partial_cmp(&other.to_rational()),
+            _ => self.to_bigint().partial_cmp(&other.to_bigint()),
+        }
+    }
+
+    /// truncated integer division, i.e. `(quotient n d)`
+    pub fn quotient(self, divisor: Value) -> Value {
+        match (self, divisor) {
+            (Integer(a), Integer(b)) => a.checked_div(b)
+                .map(Integer)
+                .unwrap_or_else(|| Bignum(BigInt::from(a) / b)),
+            (Float(a), b)            => Float((a / b.to_f64()).trunc()),
+            (a, Float(b))            => Float((a.to_f64() / b).trunc()),
+            (a, b)                   => Value::from_rational((a / b).to_rational().trunc()),
+        }
+    }
+
+    /// the remainder of floored division, which takes the sign of the divisor
+    pub fn modulo(self, modulus: Value) -> Value {
+        let negative_modulus = modulus < Integer(0);
+        let rem = self % modulus.clone();
+
+        if rem != Integer(0) && (rem < Integer(0)) != negative_modulus {
+            rem + modulus
+        } else {
+            rem
+        }
+    }
+
+    /// floored integer division, i.e. the quotient half of `(floor/ n d)`
+    pub fn floor_quotient(self, divisor: Value) -> Value {
+        let rem = self.clone().modulo(divisor.clone());
+        let quotient = (self - rem) / divisor;
+
+        // floats can be left with a tiny fractional part, so tidy it up
+        match quotient {
+            Float(n) => Float(n.round()),
+            n => n,
+        }
+    }
+
+    /// round down towards negative infinity
+    pub fn floor(&self) -> Value {
+        self.round_by(f64::floor, BigRational::floor)
+    }
+
+    /// round up towards positive infinity
+    pub fn ceiling(&self) -> Value {
+        self.round_by(f64::ceil, BigRational::ceil)
+    }
+
+    /// round towards zero
+    pub fn truncate(&self) -> Value {
+        self.round_by(f64::trunc, BigRational::trunc)
+    }
+
+    /// round to the nearest integer, breaking ties towards the even one
+    pub fn round(&self) -> Value {
+        self.round_by(round_half_even, rational_round_half_even)
+    }
+
+    /// round a number to an integer, keeping it exact if it already was
+    fn round_by(&self, float_op: fn(f64) -> f64, rational_op: fn(&BigRational) -> BigRational) -> Value {
+        match self {
+            Float(n)    => Float(float_op(*n)),
+            Rational(n) => Value::from_rational(rational_op(n)),
+            n => n.clone(),
+        }
+    }
+
+    /// absolute value
+    pub fn abs(&self) -> Value {
+        if *self < Integer(0) {
+            Integer(0) - self.clone()
+        } else {
+            self.clone()
+        }
+    }
+
+    /// square root, which stays exact if an exact number has an exact root
+    pub fn sqrt(&self) -> Value {
+        let exact_root = match self {
+            Integer(_) | Bignum(_) => exact_sqrt(&self.to_bigint()).map(Value::from_bigint),
+            Rational(n) => exact_sqrt(n.numer())
+                .zip(exact_sqrt(n.denom()))
+                .map(|(numer, denom)| Value::from_rational(BigRational::new(numer, denom))),
+            _ => None,
+        };
+
+        exact_root.unwrap_or_else(|| Float(self.to_f64().sqrt()))
+    }
+
+    /// raise a number to a power, staying exact for an exact base and an integer exponent
+    pub fn expt(&self, power: &Value) -> Value {
+        match power {
+            Integer(p) if self.is_exact() && p.unsigned_abs() <= u64::from(u32::MAX) => {
+                let base = self.to_rational();
+                let numer: BigInt = base.numer().pow(p.unsigned_abs() as u32);
+                let denom: BigInt = base.denom().pow(p.unsigned_abs() as u32);
+
+                if *p < 0 {
+                    Value::from_rational(BigRational::new(denom, numer))
+                } else {
+                    Value::from_rational(BigRational::new(numer, denom))
+                }
+            }
+            _ => Float(self.to_f64().powf(power.to_f64())),
+        }
+    }
+
+    /// greatest common divisor of two exact integers
+    pub fn gcd(&self, other: &Value) -> Value {
+        Value::from_bigint(self.to_bigint().gcd(&other.to_bigint()))
+    }
+
+    /// least common multiple of two exact integers
+    pub fn lcm(&self, other: &Value) -> Value {
+        Value::from_bigint(self.to_bigint().lcm(&other.to_bigint()))
+    }
+}
+
+/// the root of a perfect square
+fn exact_sqrt(n: &BigInt) -> Option<BigInt> {
+    if n.sign() == Sign::Minus {
+        return None;
+    }
+
+    let root = n.sqrt();
+    if &root * &root == *n {
+        Some(root)
+    } else {
+        None
+    }
+}
+
+/// round a float to the nearest integer, breaking ties towards the even one
+fn round_half_even(n: f64) -> f64 {
+    if (n - n.trunc()).abs() == 0.5 {
+        2.0 * (n / 2.0).round()
+    } else {
+        n.round()
+    }
+}
+
+/// round a rational to the nearest integer, breaking ties towards the even one
+fn rational_round_half_even(n: &BigRational) -> BigRational {
+    let floor = n.floor();
+    let half = BigRational::new(BigInt::from(1), BigInt::from(2));
+
+    match (n - &floor).cmp(&half) {
+        Ordering::Less    => floor,
+        Ordering::Greater => floor + BigInt::from(1),
+        Ordering::Equal if floor.to_integer().is_even() => floor,
+        Ordering::Equal   => floor + BigInt::from(1),
+    }
+}
+
+/// convert a `BigInt` to a float, saturating to ±INF if it's too big
+fn bigint_to_f64(n: &BigInt) -> f64 {
+    n.to_f64().unwrap_or_else(|| match n.sign() {
+        Sign::Minus => f64::NEG_INFINITY,
+        _           => f64::INFINITY,
+    })
+}
 
 impl ops::Add for Value {
     type Output = Value;
 
     fn add(self, other: Value) -> Value {
         match (self, other) {
-            (Integer(a), Integer(b)) => Integer(a + b),
-            (Float(a), Float(b))     => Float(a + b),
-            (Integer(a), Float(b))   => Float(a as f64 + b),
-            (Float(a), Integer(b))   => Float(a + (b as f64)),
-            _ => unreachable!(),
+            (Integer(a), Integer(b)) => a.checked_add(b)
+                .map(Integer)
+                .unwrap_or_else(|| Bignum(BigInt::from(a) + b)),
+            (Float(a), b)            => Float(a + b.to_f64()),
+            (a, Float(b))            => Float(a.to_f64() + b),
+            (Rational(a), b)         => Value::from_rational(a + b.to_rational()),
+            (a, Rational(b))         => Value::from_rational(a.to_rational() + b),
+            (a, b)                   => Value::from_bigint(a.to_bigint() + b.to_bigint()),
         }
     }
 }
@@ -22,11 +274,14 @@ impl ops::Sub for Value {
 
     fn sub(self, other: Value) -> Value {
         match (self, other) {
-            (Integer(a), Integer(b)) => Integer(a - b),
-            (Float(a), Float(b))     => Float(a - b),
-            (Integer(a), Float(b))   => Float(a as f64 - b),
-            (Float(a), Integer(b))   => Float(a - (b as f64)),
-            _ => unreachable!(),
+            (Integer(a), Integer(b)) => a.checked_sub(b)
+                .map(Integer)
+                .unwrap_or_else(|| Bignum(BigInt::from(a) - b)),
+            (Float(a), b)            => Float(a - b.to_f64()),
+            (a, Float(b))            => Float(a.to_f64() - b),
+            (Rational(a), b)         => Value::from_rational(a - b.to_rational()),
+            (a, Rational(b))         => Value::from_rational(a.to_rational() - b),
+            (a, b)                   => Value::from_bigint(a.to_bigint() - b.to_bigint()),
         }
     }
 }
@@ -36,11 +291,14 @@ impl ops::Mul for Value {
 
     fn mul(self, other: Value) -> Value {
         match (self, other) {
-            (Integer(a), Integer(b)) => Integer(a * b),
-            (Float(a), Float(b))     => Float(a * b),
-            (Integer(a), Float(b))   => Float(a as f64 * b),
-            (Float(a), Integer(b))   => Float(a * (b as f64)),
-            _ => unreachable!(),
+            (Integer(a), Integer(b)) => a.checked_mul(b)
+                .map(Integer)
+                .unwrap_or_else(|| Bignum(BigInt::from(a) * b)),
+            (Float(a), b)            => Float(a * b.to_f64()),
+            (a, Float(b))            => Float(a.to_f64() * b),
+            (Rational(a), b)         => Value::from_rational(a * b.to_rational()),
+            (a, Rational(b))         => Value::from_rational(a.to_rational() * b),
+            (a, b)                   => Value::from_bigint(a.to_bigint() * b.to_bigint()),
         }
     }
 }
@@ -48,13 +306,13 @@ impl ops::Mul for Value {
 impl ops::Div for Value {
     type Output = Value;
 
+    /// exact division: dividing two integers gives a `Rational` unless it comes out even
     fn div(self, other: Value) -> Value {
         match (self, other) {
-            (Integer(a), Integer(b)) => Integer(a / b),
-            (Float(a), Float(b))     => Float(a / b),
-            (Integer(a), Float(b))   => Float(a as f64 / b),
-            (Float(a), Integer(b))   => Float(a / (b as f64)),
-            (_, _) => unreachable!(),
+            (Integer(a), Integer(b)) if a.checked_rem(b) == Some(0) => Integer(a / b),
+            (Float(a), b)            => Float(a / b.to_f64()),
+            (a, Float(b))            => Float(a.to_f64() / b),
+            (a, b)                   => Value::from_rational(a.to_rational() / b.to_rational()),
         }
     }
 }
@@ -62,13 +320,16 @@ impl ops::Div for Value {
 impl ops::Rem for Value {
     type Output = Value;
 
+    /// the remainder of truncated division, which takes the sign of the dividend
     fn rem(self, modulus: Value) -> Value {
         match (self, modulus) {
-            (Integer(a), Integer(b)) => Integer(a % b),
-            (Float(a), Float(b))     => Float(a % b),
-            (Integer(a), Float(b))   => Float(a as f64 % b),
-            (Float(a), Integer(b))   => Float(a % (b as f64)),
-            (_, _) => unreachable!(),
+            // the only overflowing case is MIN % -1, which is 0 anyway
+            (Integer(a), Integer(b)) => Integer(a.checked_rem(b).unwrap_or(0)),
+            (Float(a), b)            => Float(a % b.to_f64()),
+            (a, Float(b))            => Float(a.to_f64() % b),
+            (Rational(a), b)         => Value::from_rational(a % b.to_rational()),
+            (a, Rational(b))         => Value::from_rational(a.to_rational() % b),
+            (a, b)                   => Value::from_bigint(a.to_bigint() % b.to_bigint()),
         }
     }
 }
@@ -1,51 +1,244 @@
 use failure::Error;
 use itertools;
+use num_bigint::{BigInt, Sign};
+use num_rational::BigRational;
 use rand;
 use rand::Rng;
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::convert::TryFrom;
+use std::fs;
 
 use crate::env::*;
 use crate::eval;
+use crate::image;
+use crate::modules;
+use crate::ports;
+use crate::printer;
+use crate::Interpreter;
+use crate::sandbox::Capability::{self, *};
+use crate::shared::{Lock, Shared};
 use crate::errors::RunError;
 use crate::values::Value::{self, *};
 use crate::values::LispProc;
 
-pub const BUILTINS: &[(&str, fn(Vec<Value>, EnvRef) -> Result<Value, Error>)] = &[
-    ("define",      define),
-    ("undef",       undef),
-    ("let",         local_bind),
-    ("lambda",      lambda),
-    ("if",          if_else),
-    ("cond",        cond),
-    ("type",        get_type),
-    ("quote",       quote),
-    ("eval",        eval),
-    ("env",         env),
-    ("+",           add),
-    ("-",           sub),
-    ("*",           mul),
-    ("/",           div),
-    ("modulo",      modulo),
-    ("=",           eq),
-    ("!=",          neq),
-    (">",           gt),
-    (">=",          geq),
-    ("<",           lt),
-    ("<=",          leq),
-    ("and",         and),
-    ("or",          or),
-    ("not",         not),
-    ("list-ref",    list_ref),
-    ("append",      append),
-    ("car",         car),
-    ("cdr",         cdr),
-    ("length",      length),
-    ("cons",        cons),
-    ("rand",        rand),
-    ("cat",         cat),
-    ("uppercase",   uppercase),
-    ("lowercase",   lowercase)
+/// a builtin: the name it's called by, the capability it needs, the function
+/// that runs it, and how to use it. builtins are documented here rather than
+/// on their functions, so that `>doc` can show it.
+pub struct Builtin {
+    pub name: &'static str,
+    pub capability: Capability,
+    pub run: fn(Vec<Value>, EnvRef) -> Result<Value, Error>,
+    pub doc: &'static str,
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    Builtin { name: "define",                capability: Core,    run: define,
+              doc: "(define <symbol> <value>)\n(define (<func-name> param1 param2) (<body-expr>))\n  save a value to the Env" },
+    Builtin { name: "undef",                 capability: Core,    run: undef,
+              doc: "(undef <symbol>)\n  remove a definition from the Env" },
+    Builtin { name: "let",                   capability: Core,    run: local_bind,
+              doc: "(let ((<symbol> <expr>)\n      (<symbol> <expr>)\n      (...))\n     <expr>)\n  evaluate an expression with local, unsaved bindings" },
+    Builtin { name: "lambda",                capability: Core,    run: lambda,
+              doc: "(lambda (param1 param2...) <body-expr>)\n  create a function" },
+    Builtin { name: "if",                    capability: Core,    run: if_else,
+              doc: "(if <bool-expr> <conseq-expr> <alternate-expr>)\n  conditionally evaluate an expression" },
+    Builtin { name: "cond",                  capability: Core,    run: cond,
+              doc: "(cond (<bool-expr> <conseq-expr>)\n      (<bool-expr> <conseq-expr>)\n      (...)\n      (else <alternate-expr>))\n  conditionally evaluate an expression (like branching)" },
+    Builtin { name: "type",                  capability: Core,    run: get_type,
+              doc: "(type <expr>)\n  return the the type of a value as a str" },
+    Builtin { name: "quote",                 capability: Core,    run: quote,
+              doc: "(quote <expr>)\n'<expr>\n  return an expression without evaluating it" },
+    Builtin { name: "eval",                  capability: Core,    run: eval,
+              doc: "(eval <expr>)\n  evaluate an sexp" },
+    Builtin { name: "env",                   capability: Core,    run: env,
+              doc: "(env)\n  return a list of all defined symbols" },
+    Builtin { name: "exit",                  capability: Os,      run: exit,
+              doc: "(exit)\n(exit <int>)\n(exit <bool>)\n  stop the program, with status 0 unless told otherwise" },
+    Builtin { name: "save-image",            capability: Io,      run: save_image,
+              doc: "(save-image <str>)\n  save a snapshot of every definition, including closures, to a file" },
+    Builtin { name: "load-image",            capability: Io,      run: load_image,
+              doc: "(load-image <str>)\n  replace every definition with a snapshot made by save-image" },
+    Builtin { name: "define-library",        capability: Core,    run: define_library,
+              doc: "(define-library (<name>...)\n  (export <symbol>...)\n  (import <import-set>...)\n  (begin <expr>...))\n  define a library, which can then be imported" },
+    Builtin { name: "import",                capability: Io,      run: import,
+              doc: "(import (<library name>...))\n(import (only <import-set> <symbol>...))\n(import (except <import-set> <symbol>...))\n(import (prefix <import-set> <symbol>))\n(import (rename <import-set> (<from> <to>)...))\n  bring what libraries export into scope, loading them if need be" },
+    Builtin { name: "load",                  capability: Io,      run: load,
+              doc: "(load <str>)\n  run every form in a file, defining things globally. a relative path is relative to the file that's running." },
+    Builtin { name: "include",               capability: Io,      run: include,
+              doc: "(include <str>)\n  run every form in a file as if it were written here instead, so that its definitions are local. a relative path is relative to the file that's running." },
+    Builtin { name: "+",                     capability: Math,    run: add,
+              doc: "(+ <num> <num> ...)\n  add numbers together" },
+    Builtin { name: "-",                     capability: Math,    run: sub,
+              doc: "(- <num> <num> ...)\n  subtract the rest of some numbers from the first" },
+    Builtin { name: "*",                     capability: Math,    run: mul,
+              doc: "(* <num> <num> ...)\n  multiply numbers together" },
+    Builtin { name: "/",                     capability: Math,    run: div,
+              doc: "(/ <num> <num> ...)\n  divide the first of some numbers by the rest" },
+    Builtin { name: "modulo",                capability: Math,    run: modulo,
+              doc: "(modulo <num> <num>)\n  the remainder of floored division, with the sign of the divisor" },
+    Builtin { name: "quotient",              capability: Math,    run: quotient,
+              doc: "(quotient <num> <num>)\n  integer division, rounding towards zero" },
+    Builtin { name: "remainder",             capability: Math,    run: remainder,
+              doc: "(remainder <num> <num>)\n  the remainder of truncated division, with the sign of the dividend" },
+    Builtin { name: "floor/",                capability: Math,    run: floor_div,
+              doc: "(floor/ <num> <num>)\n  floored division, returning both the quotient and the remainder" },
+    Builtin { name: "numerator",             capability: Math,    run: numerator,
+              doc: "(numerator <num>)\n  get the numerator of a number in lowest terms" },
+    Builtin { name: "denominator",           capability: Math,    run: denominator,
+              doc: "(denominator <num>)\n  get the denominator of a number in lowest terms" },
+    Builtin { name: "exact->inexact",        capability: Math,    run: exact_to_inexact,
+              doc: "(exact->inexact <num>)\n  convert an exact number to a float" },
+    Builtin { name: "inexact->exact",        capability: Math,    run: inexact_to_exact,
+              doc: "(inexact->exact <num>)\n  convert a float to the exact number it represents" },
+    Builtin { name: "sqrt",                  capability: Math,    run: sqrt,
+              doc: "(sqrt <num>)\n  square root, exact when the number is an exact square" },
+    Builtin { name: "expt",                  capability: Math,    run: expt,
+              doc: "(expt <num> <num>)\n  raise a number to a power" },
+    Builtin { name: "exp",                   capability: Math,    run: exp,
+              doc: "(exp <num>)\n  e to the power of a number" },
+    Builtin { name: "log",                   capability: Math,    run: log,
+              doc: "(log <num>)\n(log <num> <base>)\n  natural logarithm, or the logarithm in some base" },
+    Builtin { name: "sin",                   capability: Math,    run: sin,
+              doc: "(sin <num>)\n  sine of an angle in radians" },
+    Builtin { name: "cos",                   capability: Math,    run: cos,
+              doc: "(cos <num>)\n  cosine of an angle in radians" },
+    Builtin { name: "tan",                   capability: Math,    run: tan,
+              doc: "(tan <num>)\n  tangent of an angle in radians" },
+    Builtin { name: "atan",                  capability: Math,    run: atan,
+              doc: "(atan <num>)\n(atan <y> <x>)\n  arctangent, or the angle of the point (x, y) when given two arguments" },
+    Builtin { name: "floor",                 capability: Math,    run: floor,
+              doc: "(floor <num>)\n  round a number down" },
+    Builtin { name: "ceiling",               capability: Math,    run: ceiling,
+              doc: "(ceiling <num>)\n  round a number up" },
+    Builtin { name: "round",                 capability: Math,    run: round,
+              doc: "(round <num>)\n  round a number to the nearest integer, and halves to even" },
+    Builtin { name: "truncate",              capability: Math,    run: truncate,
+              doc: "(truncate <num>)\n  round a number towards zero" },
+    Builtin { name: "abs",                   capability: Math,    run: abs,
+              doc: "(abs <num>)\n  the absolute value of a number" },
+    Builtin { name: "min",                   capability: Math,    run: min,
+              doc: "(min <num> <num> ...)\n  the smallest of some numbers, inexact if any of them are" },
+    Builtin { name: "max",                   capability: Math,    run: max,
+              doc: "(max <num> <num> ...)\n  the largest of some numbers, inexact if any of them are" },
+    Builtin { name: "gcd",                   capability: Math,    run: gcd,
+              doc: "(gcd <int> <int> ...)\n  greatest common divisor of some integers" },
+    Builtin { name: "lcm",                   capability: Math,    run: lcm,
+              doc: "(lcm <int> <int> ...)\n  least common multiple of some integers" },
+    Builtin { name: "exact-integer-sqrt",    capability: Math,    run: exact_integer_sqrt,
+              doc: "(exact-integer-sqrt <int>)\n  the integer square root of a number, and what's left over" },
+    Builtin { name: "number->string",        capability: Math,    run: number_to_string,
+              doc: "(number->string <num>)\n(number->string <int> <radix>)\n  convert a number to a string, optionally writing an integer in another radix" },
+    Builtin { name: "bitwise-and",           capability: Math,    run: bitwise_and,
+              doc: "(bitwise-and <int> <int> ...)\n  the bits set in every one of some integers" },
+    Builtin { name: "bitwise-or",            capability: Math,    run: bitwise_or,
+              doc: "(bitwise-or <int> <int> ...)\n  the bits set in any of some integers" },
+    Builtin { name: "bitwise-xor",           capability: Math,    run: bitwise_xor,
+              doc: "(bitwise-xor <int> <int> ...)\n  the bits set in an odd number of some integers" },
+    Builtin { name: "bitwise-not",           capability: Math,    run: bitwise_not,
+              doc: "(bitwise-not <int>)\n  flip every bit of an integer" },
+    Builtin { name: "arithmetic-shift",      capability: Math,    run: arithmetic_shift,
+              doc: "(arithmetic-shift <int> <int>)\n  shift an integer left by some number of bits, or right if it's negative" },
+    Builtin { name: "bit-count",             capability: Math,    run: bit_count,
+              doc: "(bit-count <int>)\n  count the 1 bits in a positive integer, or the 0 bits in a negative one" },
+    Builtin { name: "number?",               capability: Math,    run: is_number,
+              doc: "(number? <expr>)\n  test whether a value is a number" },
+    Builtin { name: "integer?",              capability: Math,    run: is_integer,
+              doc: "(integer? <expr>)\n  test whether a value is an integer, including floats like 2.0" },
+    Builtin { name: "rational?",             capability: Math,    run: is_rational,
+              doc: "(rational? <expr>)\n  test whether a value is a rational number, which every finite number is" },
+    Builtin { name: "exact?",                capability: Math,    run: is_exact,
+              doc: "(exact? <num>)\n  test whether a number is exact, i.e. not a float" },
+    Builtin { name: "inexact?",              capability: Math,    run: is_inexact,
+              doc: "(inexact? <num>)\n  test whether a number is a float" },
+    Builtin { name: "zero?",                 capability: Math,    run: is_zero,
+              doc: "(zero? <num>)\n  test whether a number is zero" },
+    Builtin { name: "positive?",             capability: Math,    run: is_positive,
+              doc: "(positive? <num>)\n  test whether a number is greater than zero" },
+    Builtin { name: "negative?",             capability: Math,    run: is_negative,
+              doc: "(negative? <num>)\n  test whether a number is less than zero" },
+    Builtin { name: "odd?",                  capability: Math,    run: is_odd,
+              doc: "(odd? <int>)\n  test whether an integer is odd" },
+    Builtin { name: "even?",                 capability: Math,    run: is_even,
+              doc: "(even? <int>)\n  test whether an integer is even" },
+    Builtin { name: "nan?",                  capability: Math,    run: is_nan,
+              doc: "(nan? <num>)\n  test whether a number is NaN" },
+    Builtin { name: "infinite?",             capability: Math,    run: is_infinite,
+              doc: "(infinite? <num>)\n  test whether a number is infinite" },
+    Builtin { name: "finite?",               capability: Math,    run: is_finite,
+              doc: "(finite? <num>)\n  test whether a number is neither infinite nor NaN" },
+    Builtin { name: "=",                     capability: Math,    run: eq,
+              doc: "(= <num> <num> ...)\n  test whether numbers are all equal" },
+    Builtin { name: "!=",                    capability: Core,    run: neq,
+              doc: "(!= <expr> <expr>)\n  test whether two values of any type are different" },
+    Builtin { name: "equal?",                capability: Core,    run: equal,
+              doc: "(equal? <expr> <expr>)\n  test whether two values of any type are the same" },
+    Builtin { name: ">",                     capability: Math,    run: gt,
+              doc: "(> <num> <num> ...)\n  test whether numbers are in decreasing order" },
+    Builtin { name: ">=",                    capability: Math,    run: geq,
+              doc: "(>= <num> <num> ...)\n  test whether numbers are in non-increasing order" },
+    Builtin { name: "<",                     capability: Math,    run: lt,
+              doc: "(< <num> <num> ...)\n  test whether numbers are in increasing order" },
+    Builtin { name: "<=",                    capability: Math,    run: leq,
+              doc: "(<= <num> <num> ...)\n  test whether numbers are in non-decreasing order" },
+    Builtin { name: "and",                   capability: Core,    run: and,
+              doc: "(and <expr> <expr> ...)\n  evaluate expressions until one of them is false, returning the last value evaluated, or #t if there are none" },
+    Builtin { name: "or",                    capability: Core,    run: or,
+              doc: "(or <expr> <expr> ...)\n  evaluate expressions until one of them is true, returning that value, or #f if there are none" },
+    Builtin { name: "not",                   capability: Core,    run: not,
+              doc: "(not <bool>)\n  return the logical inverse of a bool" },
+    Builtin { name: "list-ref",              capability: Core,    run: list_ref,
+              doc: "(list-ref <list> <int>)\n  get the item at an index in a list, counting from 1" },
+    Builtin { name: "append",                capability: Core,    run: append,
+              doc: "(append <list> <list>)\n  concatenate two lists together" },
+    Builtin { name: "car",                   capability: Core,    run: car,
+              doc: "(car <list>)\n  return the first element of a populated list, or nil" },
+    Builtin { name: "cdr",                   capability: Core,    run: cdr,
+              doc: "(cdr <list>)\n  return all elements of a list but the first" },
+    Builtin { name: "length",                capability: Core,    run: length,
+              doc: "(length <list>)\n(length <str>)\n  get the length of a list or a string" },
+    Builtin { name: "cons",                  capability: Core,    run: cons,
+              doc: "(cons <value> <list>)\n  construct a list" },
+    Builtin { name: "rand",                  capability: Core,    run: rand,
+              doc: "(rand <list>)\n(rand <expr> <expr> ...)\n  return a random argument or a random element of a list" },
+    Builtin { name: "open-input-file",       capability: Io,      run: open_input_file,
+              doc: "(open-input-file <str>)\n  open a file to read from" },
+    Builtin { name: "open-output-file",      capability: Io,      run: open_output_file,
+              doc: "(open-output-file <str>)\n  open a file to write to, replacing whatever was in it" },
+    Builtin { name: "call-with-output-file", capability: Io,      run: call_with_output_file,
+              doc: "(call-with-output-file <str> <proc>)\n  call a procedure with a port for writing to a file, and close it afterwards" },
+    Builtin { name: "current-input-port",    capability: Core,    run: current_input_port,
+              doc: "(current-input-port)\n  the port that input comes from when no other is given" },
+    Builtin { name: "current-output-port",   capability: Core,    run: current_output_port,
+              doc: "(current-output-port)\n  the port that output goes to when no other is given" },
+    Builtin { name: "current-error-port",    capability: Core,    run: current_error_port,
+              doc: "(current-error-port)\n  the port for error messages" },
+    Builtin { name: "read-line",             capability: Core,    run: read_line,
+              doc: "(read-line)\n(read-line <port>)\n  read the rest of a line, or eof at the end of the input" },
+    Builtin { name: "read-char",             capability: Core,    run: read_char,
+              doc: "(read-char)\n(read-char <port>)\n  read one character, as a string, or eof at the end of the input" },
+    Builtin { name: "read",                  capability: Core,    run: read,
+              doc: "(read)\n(read <port>)\n  read one expression, without evaluating it, or eof at the end of the input" },
+    Builtin { name: "write",                 capability: Core,    run: write,
+              doc: "(write <expr>)\n(write <expr> <port>)\n  write a value so that it could be read back in, e.g. strings in quotes" },
+    Builtin { name: "display",               capability: Core,    run: display,
+              doc: "(display <expr>)\n(display <expr> <port>)\n  write a value for people to read, e.g. strings without quotes" },
+    Builtin { name: "pp",                    capability: Core,    run: pp,
+              doc: "(pp <expr>)\n(pp <expr> <port>)\n  write a value, broken over several lines and indented if it's too wide for one, and end the line" },
+    Builtin { name: "newline",               capability: Core,    run: newline,
+              doc: "(newline)\n(newline <port>)\n  end the line" },
+    Builtin { name: "close-port",            capability: Core,    run: close_port,
+              doc: "(close-port <port>)\n  stop using a port, closing its file" },
+    Builtin { name: "with-output-to-string", capability: Core,    run: with_output_to_string,
+              doc: "(with-output-to-string <proc>)\n  call a procedure, and return everything it printed as a string" },
+    Builtin { name: "eof-object",            capability: Core,    run: eof_object,
+              doc: "(eof-object)\n  what reading at the end of the input gives" },
+    Builtin { name: "eof-object?",           capability: Core,    run: is_eof_object,
+              doc: "(eof-object? <expr>)\n  has reading got to the end of the input?" },
+    Builtin { name: "cat",                   capability: Strings, run: cat,
+              doc: "(cat <value> <value> ...)\n  concatenate values together into a string" },
+    Builtin { name: "uppercase",             capability: Strings, run: uppercase,
+              doc: "(uppercase <str>)\n  translate the characters in a string to uppercase" },
+    Builtin { name: "lowercase",             capability: Strings, run: lowercase,
+              doc: "(lowercase <str>)\n  translate the characters in a string to lowercase" },
 ];
 
 // {{{ helpful macros
@@ -112,9 +305,6 @@ macro_rules! success {
 // }}}
 
 // {{{ essentials
-/// save a value to the Env
-/// usage: (define <symbol> <value>)
-///        (define (<func-name> param1 param2) (<body-expr>))
 pub fn define(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 2, "define")?;
 
@@ -122,7 +312,7 @@ pub fn define(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
         Symbol(var_name) => {
             let expr = args[1].clone();
             let expr_result = eval::eval(expr, env.clone())?;
-            env.borrow_mut().define(var_name, expr_result);
+            env.borrow_mut().define(var_name, expr_result)?;
             success!()
         },
 
@@ -135,7 +325,7 @@ pub fn define(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
             let proc_name: String = extract!(list.remove(0), Symbol, "define")?;
             let body = args[1].clone();
             let procedure = lambda(vec![List(list.to_vec()), body], env.clone())?;
-            env.borrow_mut().define(&proc_name, procedure);
+            env.borrow_mut().define(&proc_name, procedure)?;
             success!()
         },
 
@@ -143,8 +333,6 @@ pub fn define(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     }
 }
 
-/// remove a definition from the Env
-/// usage: (undef <symbol>)
 pub fn undef(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 1, "undef")?;
 
@@ -153,11 +341,6 @@ pub fn undef(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     success!()
 }
 
-/// evaluate an expression with local, unsaved bindings
-/// usage: (let ((<symbol> <expr>)
-///              (<symbol> <expr>)
-///              (...))
-///             <expr>)
 pub fn local_bind(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 2, "let")?;
 
@@ -172,15 +355,13 @@ pub fn local_bind(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
         let expr = &bind[1];
         let expr_result = eval::eval(expr.clone(), env.clone())?;
 
-        local_env.define(&var_name, expr_result);
+        local_env.define(&var_name, expr_result)?;
     }
 
-    let local_env_ref: EnvRef = Rc::new(RefCell::new(local_env));
+    let local_env_ref: EnvRef = Shared::new(Lock::new(local_env));
     eval::eval(args[1].clone(), local_env_ref)
 }
 
-/// create a function
-/// usage: (lambda (param1 param2...) <body-expr>)
 pub fn lambda(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 2, "lambda")?;
 
@@ -200,23 +381,16 @@ pub fn lambda(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     }))
 }
 
-/// conditionally evaluate an expression
-/// usage: (if <bool-expr> <conseq-expr> <alternate-expr>)
 pub fn if_else(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 3, "if")?;
 
-    let test = eval::eval(args[0].clone(), env.clone())?.to_bool();
+    let test = eval::eval(args[0].clone(), env.clone())?.to_bool(env.borrow().truthiness);
     let conseq = args[1].clone();
     let alt = args[2].clone();
 
     eval::eval(if test { conseq } else { alt }, env.clone())
 }
 
-/// conditionally evaluate an expression (like branching)
-/// usage: (cond (<bool-expr> <conseq-expr>)
-///              (<bool-expr> <conseq-expr>)
-///              (...)
-///              (else <alternate-expr>))
 pub fn cond(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     if args.is_empty() {
         return procerr!("cond", "at least 1 branch required");
@@ -226,7 +400,7 @@ pub fn cond(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
         let branch: Vec<Value> = extract!(branch, List, "cond")?;
         check_num_args!(branch, 2, "cond (in branch)")?;
 
-        if eval::eval(branch[0].clone(), env.clone())?.to_bool() {
+        if eval::eval(branch[0].clone(), env.clone())?.to_bool(env.borrow().truthiness) {
             return eval::eval(branch[1].clone(), env.clone());
         }
     }
@@ -234,8 +408,6 @@ pub fn cond(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     procerr!("cond", "no branches evaluated and no `else` branch found")
 }
 
-/// return the the type of a value as a str
-/// usage: (type <expr>)
 pub fn get_type(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 1, "type")?;
 
@@ -243,24 +415,17 @@ pub fn get_type(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     Ok(Str(thingtype))
 }
 
-/// return an expression without evaluating it
-/// usage: (quote <expr>)
-///        '<expr>
 pub fn quote(mut args: Vec<Value>, _env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 1, "quote")?;
     Ok(args.pop().unwrap())
 }
 
-/// evaluate an sexp
-/// usage: (eval <expr>)
 pub fn eval(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 1, "eval")?;
     args = eval::eval_list(args, env.clone())?;
     eval::eval(args[0].clone(), env)
 }
 
-/// return a list of all defined symbols
-/// usage: (env)
 pub fn env(_args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     Ok(List(
         env.borrow()
@@ -270,40 +435,135 @@ pub fn env(_args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
             .collect()
     ))
 }
+
+pub fn save_image(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 1, "save-image")?;
+    args = eval::eval_list(args, env.clone())?;
+
+    let path = extract!(&args[0], &Str, "save-image")?;
+    fs::write(path, image::dump(&image::global_env(&env)))?;
+    success!()
+}
+
+pub fn load_image(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 1, "load-image")?;
+    args = eval::eval_list(args, env.clone())?;
+
+    let path = extract!(&args[0], &Str, "load-image")?;
+    image::restore(&fs::read_to_string(path)?, &image::global_env(&env))?;
+    success!()
+}
+
+pub fn define_library(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    if args.is_empty() {
+        return Err(RunError::WrongNumArgs {
+            name: "define-library".to_string(),
+            expected: 1,
+            got: 0,
+        }.into());
+    }
+
+    let name = args.remove(0);
+    modules::define_library(&name, args, &env)?;
+    success!()
+}
+
+pub fn import(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    for set in &args {
+        modules::import(set, &env)?;
+    }
+    success!()
+}
+
+pub fn load(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    run_file("load", args, image::global_env(&env))
+}
+
+pub fn include(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    run_file("include", args, env)
+}
+
+fn run_file(name: &str, mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 1, name)?;
+    args = eval::eval_list(args, env.clone())?;
+
+    let path = extract!(&args[0], &Str, name)?;
+    let path = env.borrow().modules.relative_path(&path);
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(err) => return procerr!(name, format!("couldn't read {}: {}", path.display(), err)),
+    };
+
+    Interpreter { env }.run_source(&source, &path.to_string_lossy(), false)?;
+    success!()
+}
+
+pub fn exit(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    if args.len() > 1 {
+        return Err(RunError::WrongNumArgs {
+            name: "exit".to_string(),
+            expected: 1,
+            got: args.len(),
+        }.into());
+    }
+    args = eval::eval_list(args, env)?;
+
+    let status = match args.pop() {
+        None              => 0,
+        Some(Bool(true))  => 0,
+        Some(Bool(false)) => 1,
+        Some(Integer(n))  => match i32::try_from(n) {
+            Ok(n)  => n,
+            Err(_) => return procerr!("exit", format!("status {} is out of range", n)),
+        },
+        Some(value) => return Err(RunError::TypeError {
+            name: "exit".to_string(),
+            expected: "Integer".to_string(),
+            got: value.get_type(),
+        }.into()),
+    };
+
+    Err(RunError::Exit(status).into())
+}
 // }}}
 
 // {{{ math
+/// return a TypeError if any of the arguments aren't numbers
+fn check_numbers(name: &str, args: &[Value]) -> Result<(), Error> {
+    match args.iter().find(|arg| !arg.is_number()) {
+        Some(arg) => Err(RunError::TypeError {
+            name: name.to_string(),
+            expected: "number".to_string(),
+            got: arg.get_type(),
+        }.into()),
+        None => Ok(()),
+    }
+}
+
 /// do some math
 /// usage: (+ <num> <num>)
 ///        (- <num> <num>)
 ///        (* <num> <num>)
 ///        (/ <num> <num>)
-///        (^ <num> <num>)
+///        (modulo <num> <num>)
+///        (quotient <num> <num>)
+///        (remainder <num> <num>)
 fn math(op: &str, mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     if args.len() < 2 {
         return procerr!(op, "at least 2 arguments required");
     }
 
-    args = eval::eval_list(args, env)?;
-    // make sure all arguments are floats or integers
-    for arg in &args {
-        match arg {
-            Integer(_) => continue,
-            Float(_)   => continue,
-            _ => {
-                return Err(RunError::TypeError {
-                    name: op.to_string(),
-                    expected: "number".to_string(),
-                    got: arg.get_type(),
-                }.into());
-            }
-        }
+    let integer_division = op == "modulo" || op == "quotient" || op == "remainder";
+    if integer_division {
+        check_num_args!(args, 2, op)?;
     }
 
-    if op == "/" || op == "%" {
-        if args.iter().skip(1).find(|&x| x == &Integer(0)).is_some() {
-            return Err(RunError::DivideByZero.into());
-        }
+    args = eval::eval_list(args, env)?;
+    // make sure all arguments are numbers
+    check_numbers(op, &args)?;
+
+    if (op == "/" || integer_division) && args.iter().skip(1).any(|x| x == &Integer(0)) {
+        return Err(RunError::DivideByZero.into());
     }
 
     let init = args.remove(0);
@@ -312,7 +572,9 @@ fn math(op: &str, mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
         "-" => args.into_iter().fold(init, |acc, n| acc - n),
         "*" => args.into_iter().fold(init, |acc, n| acc * n),
         "/" => args.into_iter().fold(init, |acc, n| acc / n),
-        "%" => init % args.remove(0),
+        "modulo"    => init.modulo(args.remove(0)),
+        "quotient"  => init.quotient(args.remove(0)),
+        "remainder" => init % args.remove(0),
         _ => panic!("'{}' is not a valid math operator, check your code!", op),
     };
 
@@ -336,82 +598,573 @@ pub fn div(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
 }
 
 pub fn modulo(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
-    math("%", args, env)
+    math("modulo", args, env)
+}
+
+pub fn quotient(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    math("quotient", args, env)
+}
+
+pub fn remainder(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    math("remainder", args, env)
+}
+
+pub fn floor_div(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 2, "floor/")?;
+
+    args = eval::eval_list(args, env)?;
+    check_numbers("floor/", &args)?;
+    if args[1] == Integer(0) {
+        return Err(RunError::DivideByZero.into());
+    }
+
+    let divisor = args.pop().unwrap();
+    let dividend = args.pop().unwrap();
+    let quotient = dividend.clone().floor_quotient(divisor.clone());
+    let remainder = dividend.modulo(divisor);
+    Ok(List(vec![quotient, remainder]))
+}
+
+pub fn numerator(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 1, "numerator")?;
+
+    args = eval::eval_list(args, env)?;
+    check_numbers("numerator", &args)?;
+    match args.pop().unwrap() {
+        Rational(n) => Ok(Value::from_bigint(n.numer().clone())),
+        Float(n) => match BigRational::from_float(n) {
+            Some(n) => Ok(Float(Value::from_bigint(n.numer().clone()).to_f64())),
+            None    => procerr!("numerator", format!("{} has no numerator", n)),
+        },
+        n => Ok(n),
+    }
+}
+
+pub fn denominator(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 1, "denominator")?;
+
+    args = eval::eval_list(args, env)?;
+    check_numbers("denominator", &args)?;
+    match args.pop().unwrap() {
+        Rational(n) => Ok(Value::from_bigint(n.denom().clone())),
+        Float(n) => match BigRational::from_float(n) {
+            Some(n) => Ok(Float(Value::from_bigint(n.denom().clone()).to_f64())),
+            None    => procerr!("denominator", format!("{} has no denominator", n)),
+        },
+        _ => Ok(Integer(1)),
+    }
+}
+
+pub fn exact_to_inexact(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 1, "exact->inexact")?;
+
+    args = eval::eval_list(args, env)?;
+    check_numbers("exact->inexact", &args)?;
+    Ok(Float(args[0].to_f64()))
+}
+
+pub fn inexact_to_exact(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 1, "inexact->exact")?;
+
+    args = eval::eval_list(args, env)?;
+    check_numbers("inexact->exact", &args)?;
+    match args.pop().unwrap() {
+        Float(n) => match BigRational::from_float(n) {
+            Some(n) => Ok(Value::from_rational(n)),
+            None    => Err(RunError::NoExactRepresentation(Float(n).to_string()).into()),
+        },
+        n => Ok(n),
+    }
 }
 // }}}
 
-// {{{ logic
-/// do some boolean logic
-/// usage: (= <expr> <expr>)
-///        (!= <expr> <expr>)
-///        (> <num> <num>)
-///        (>= <num> <num>)
-///        (< <num> <num>)
-///        (<= <num> <num>)
-///        (and <bool> <bool>)
-///        (or <bool> <bool>)
-fn logic(op: &str, mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
-    check_num_args!(args, 2, format!("logic op `{}`", op))?;
+// {{{ number functions
+/// evaluate the arguments to a numeric function, checking there are $num of them
+fn number_args(name: &str, args: Vec<Value>, num: usize, env: EnvRef) -> Result<Vec<Value>, Error> {
+    check_num_args!(args, num, name)?;
+
+    let args = eval::eval_list(args, env)?;
+    check_numbers(name, &args)?;
+    Ok(args)
+}
+
+/// return a TypeError if any of the arguments aren't exact integers
+fn check_integers(name: &str, args: &[Value]) -> Result<(), Error> {
+    match args.iter().find(|arg| !(arg.is_exact() && arg.is_integer())) {
+        Some(arg) => Err(RunError::TypeError {
+            name: name.to_string(),
+            expected: "exact integer".to_string(),
+            got: arg.get_type(),
+        }.into()),
+        None => Ok(()),
+    }
+}
+
+/// apply a function of one number
+/// usage: (sqrt <num>)
+///        (exp <num>)
+///        (sin <num>)
+///        (cos <num>)
+///        (tan <num>)
+///        (floor <num>)
+///        (ceiling <num>)
+///        (round <num>)
+///        (truncate <num>)
+///        (abs <num>)
+fn unary_math(op: &str, args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    let n = number_args(op, args, 1, env)?.remove(0);
+
+    let result = match op {
+        "sqrt"     => n.sqrt(),
+        "exp"      => Float(n.to_f64().exp()),
+        "sin"      => Float(n.to_f64().sin()),
+        "cos"      => Float(n.to_f64().cos()),
+        "tan"      => Float(n.to_f64().tan()),
+        "floor"    => n.floor(),
+        "ceiling"  => n.ceiling(),
+        "round"    => n.round(),
+        "truncate" => n.truncate(),
+        "abs"      => n.abs(),
+        _ => panic!("'{}' is not a valid math function, check your code!", op),
+    };
+
+    Ok(result)
+}
+
+pub fn sqrt(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    unary_math("sqrt", args, env)
+}
+
+pub fn exp(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    unary_math("exp", args, env)
+}
+
+pub fn sin(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    unary_math("sin", args, env)
+}
+
+pub fn cos(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    unary_math("cos", args, env)
+}
+
+pub fn tan(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    unary_math("tan", args, env)
+}
+
+pub fn floor(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    unary_math("floor", args, env)
+}
+
+pub fn ceiling(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    unary_math("ceiling", args, env)
+}
+
+pub fn round(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    unary_math("round", args, env)
+}
+
+pub fn truncate(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    unary_math("truncate", args, env)
+}
+
+pub fn abs(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    unary_math("abs", args, env)
+}
+
+pub fn expt(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    let args = number_args("expt", args, 2, env)?;
+
+    if args[0] == Integer(0) && args[1].is_exact() && args[1] < Integer(0) {
+        return Err(RunError::DivideByZero.into());
+    }
+
+    // num-bigint can't be stopped partway through, so don't start on a power
+    // that would come out too big. 0, 1 and -1 stay small however high they go.
+    if let (true, Integer(p)) = (args[0].is_exact(), &args[1]) {
+        let base = args[0].to_rational();
+        let bits = base.numer().bits().max(base.denom().bits());
+        if bits > 1 && bits.saturating_mul(p.unsigned_abs() as usize) > MAX_POWER_BITS {
+            return procerr!("expt", format!("{} to the power of {} is too big", args[0], p));
+        }
+    }
+
+    Ok(args[0].expt(&args[1]))
+}
+
+/// the most bits an exact `expt` will make a number, which is 2MB of it
+const MAX_POWER_BITS: usize = 1 << 24;
+
+pub fn log(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    if args.is_empty() || args.len() > 2 {
+        return procerr!("log", "expected 1 or 2 arguments");
+    }
+
+    args = eval::eval_list(args, env)?;
+    check_numbers("log", &args)?;
+
+    match args.get(1) {
+        Some(base) => Ok(Float(args[0].to_f64().ln() / base.to_f64().ln())),
+        None       => Ok(Float(args[0].to_f64().ln())),
+    }
+}
+
+pub fn atan(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    if args.is_empty() || args.len() > 2 {
+        return procerr!("atan", "expected 1 or 2 arguments");
+    }
+
+    args = eval::eval_list(args, env)?;
+    check_numbers("atan", &args)?;
+
+    match args.get(1) {
+        Some(x) => Ok(Float(args[0].to_f64().atan2(x.to_f64()))),
+        None    => Ok(Float(args[0].to_f64().atan())),
+    }
+}
+
+/// the smallest or largest of some numbers. if any of them are
+/// inexact, so is the result.
+/// usage: (min <num> <num> ...)
+///        (max <num> <num> ...)
+fn extremum(op: &str, mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    if args.is_empty() {
+        return procerr!(op, "at least 1 argument required");
+    }
 
     args = eval::eval_list(args, env)?;
+    check_numbers(op, &args)?;
+
+    let inexact = args.iter().any(|n| !n.is_exact());
+    let init = args.remove(0);
+    let result = args.into_iter().fold(init, |acc, n| match op {
+        "min" => if n < acc { n } else { acc },
+        "max" => if n > acc { n } else { acc },
+        _ => panic!("'{}' is not a valid extremum, check your code!", op),
+    });
+
+    Ok(if inexact { Float(result.to_f64()) } else { result })
+}
+
+pub fn min(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    extremum("min", args, env)
+}
+
+pub fn max(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    extremum("max", args, env)
+}
+
+pub fn gcd(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    args = eval::eval_list(args, env)?;
+    check_integers("gcd", &args)?;
+    Ok(args.iter().fold(Integer(0), |acc, n| acc.gcd(n)))
+}
+
+pub fn lcm(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    args = eval::eval_list(args, env)?;
+    check_integers("lcm", &args)?;
+    Ok(args.iter().fold(Integer(1), |acc, n| acc.lcm(n)))
+}
+
+pub fn exact_integer_sqrt(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    let args = number_args("exact-integer-sqrt", args, 1, env)?;
+    check_integers("exact-integer-sqrt", &args)?;
+    if args[0] < Integer(0) {
+        return procerr!("exact-integer-sqrt", "argument must not be negative");
+    }
+
+    let n = args[0].to_bigint();
+    let root = n.sqrt();
+    let rest = n - &root * &root;
+    Ok(List(vec![Value::from_bigint(root), Value::from_bigint(rest)]))
+}
+
+pub fn number_to_string(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    if args.is_empty() || args.len() > 2 {
+        return procerr!("number->string", "expected 1 or 2 arguments");
+    }
+
+    args = eval::eval_list(args, env)?;
+    check_numbers("number->string", &args)?;
+
+    let radix = match args.get(1) {
+        Some(Integer(r)) if [2, 8, 10, 16].contains(r) => *r as u32,
+        Some(r) => return procerr!("number->string", format!("unsupported radix {}", r)),
+        None => 10,
+    };
+
+    if radix == 10 {
+        Ok(Str(args[0].to_string()))
+    } else {
+        check_integers("number->string", &args[..1])?;
+        Ok(Str(args[0].to_bigint().to_str_radix(radix)))
+    }
+}
+
+/// test some property of a number
+/// usage: (number? <expr>)
+///        (integer? <expr>)
+///        (rational? <expr>)
+///        (exact? <num>)
+///        (inexact? <num>)
+///        (zero? <num>)
+///        (positive? <num>)
+///        (negative? <num>)
+///        (odd? <int>)
+///        (even? <int>)
+///        (nan? <num>)
+///        (infinite? <num>)
+///        (finite? <num>)
+fn number_predicate(op: &str, mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 1, op)?;
+
+    args = eval::eval_list(args, env)?;
+    let n = args.remove(0);
+
+    // these ones work on anything
     match op {
-        "="   => Ok(Bool(args[0] == args[1])),
-        "!="  => Ok(Bool(args[0] != args[1])),
-        ">"   => Ok(Bool(args[0] >  args[1])),
-        ">="  => Ok(Bool(args[0] >= args[1])),
-        "<"   => Ok(Bool(args[0] <  args[1])),
-        "<="  => Ok(Bool(args[0] <= args[1])),
-        "and" => Ok(Bool(args[0].to_bool() && args[1].to_bool())),
-        "or"  => Ok(Bool(args[0].to_bool() || args[1].to_bool())),
-        _     => panic!("{} is not a valid comparison operator", op),
+        "number?"   => return Ok(Bool(n.is_number())),
+        "integer?"  => return Ok(Bool(n.is_integer())),
+        "rational?" => return Ok(Bool(n.is_exact() || n.is_number() && n.to_f64().is_finite())),
+        _ => check_numbers(op, std::slice::from_ref(&n))?,
     }
+
+    let result = match op {
+        "exact?"    => n.is_exact(),
+        "inexact?"  => !n.is_exact(),
+        "zero?"     => n == Integer(0),
+        "positive?" => n > Integer(0),
+        "negative?" => n < Integer(0),
+        "odd?"      => n.is_integer() && n.modulo(Integer(2)) == Integer(1),
+        "even?"     => n.is_integer() && n.modulo(Integer(2)) == Integer(0),
+        "nan?"      => n.to_f64().is_nan(),
+        "infinite?" => n.to_f64().is_infinite() && !n.is_exact(),
+        "finite?"   => n.is_exact() || n.to_f64().is_finite(),
+        _ => panic!("'{}' is not a valid number predicate, check your code!", op),
+    };
+
+    Ok(Bool(result))
 }
 
-pub fn eq(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
-    logic("=", args, env)
+pub fn is_number(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    number_predicate("number?", args, env)
+}
+
+pub fn is_integer(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    number_predicate("integer?", args, env)
+}
+
+pub fn is_rational(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    number_predicate("rational?", args, env)
+}
+
+pub fn is_exact(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    number_predicate("exact?", args, env)
+}
+
+pub fn is_inexact(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    number_predicate("inexact?", args, env)
+}
+
+pub fn is_zero(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    number_predicate("zero?", args, env)
+}
+
+pub fn is_positive(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    number_predicate("positive?", args, env)
+}
+
+pub fn is_negative(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    number_predicate("negative?", args, env)
+}
+
+pub fn is_odd(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    number_predicate("odd?", args, env)
+}
+
+pub fn is_even(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    number_predicate("even?", args, env)
+}
+
+pub fn is_nan(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    number_predicate("nan?", args, env)
+}
+
+pub fn is_infinite(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    number_predicate("infinite?", args, env)
+}
+
+pub fn is_finite(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    number_predicate("finite?", args, env)
+}
+// }}}
+
+// {{{ bitwise
+/// fold some integers together bit by bit, treating them as infinitely
+/// sign-extended two's complement numbers
+/// usage: (bitwise-and <int> <int> ...)
+///        (bitwise-or <int> <int> ...)
+///        (bitwise-xor <int> <int> ...)
+fn bitwise(op: &str, mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    args = eval::eval_list(args, env)?;
+    check_integers(op, &args)?;
+
+    let ints = args.iter().map(Value::to_bigint);
+    let result = match op {
+        "bitwise-and" => ints.fold(BigInt::from(-1), |acc, n| acc & n),
+        "bitwise-or"  => ints.fold(BigInt::from(0), |acc, n| acc | n),
+        "bitwise-xor" => ints.fold(BigInt::from(0), |acc, n| acc ^ n),
+        _ => panic!("'{}' is not a valid bitwise operator, check your code!", op),
+    };
+
+    Ok(Value::from_bigint(result))
+}
+
+pub fn bitwise_and(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    bitwise("bitwise-and", args, env)
+}
+
+pub fn bitwise_or(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    bitwise("bitwise-or", args, env)
+}
+
+pub fn bitwise_xor(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    bitwise("bitwise-xor", args, env)
+}
+
+pub fn bitwise_not(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    let args = number_args("bitwise-not", args, 1, env)?;
+    check_integers("bitwise-not", &args)?;
+    Ok(Value::from_bigint(!args[0].to_bigint()))
+}
+
+/// the most bits `arithmetic-shift` will make a number, which is 2MB of it
+const MAX_SHIFTED_BITS: usize = 1 << 24;
+
+pub fn arithmetic_shift(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    let args = number_args("arithmetic-shift", args, 2, env)?;
+    check_integers("arithmetic-shift", &args)?;
+
+    let n = args[0].to_bigint();
+    let shift = extract!(args[1], Integer, "arithmetic-shift")?;
+
+    if shift >= 0 {
+        if n.bits().saturating_add(shift as usize) > MAX_SHIFTED_BITS {
+            return procerr!("arithmetic-shift", format!("can't shift by {} bits", shift));
+        }
+        Ok(Value::from_bigint(n << shift as usize))
+    } else {
+        Ok(Value::from_bigint(n >> shift.unsigned_abs() as usize))
+    }
+}
+
+pub fn bit_count(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    let args = number_args("bit-count", args, 1, env)?;
+    check_integers("bit-count", &args)?;
+
+    let mut n = args[0].to_bigint();
+    if n.sign() == Sign::Minus {
+        n = !n;
+    }
+
+    let (_, bytes) = n.to_bytes_le();
+    Ok(Integer(bytes.iter().map(|b| i64::from(b.count_ones())).sum()))
 }
+// }}}
+
+// {{{ logic
+/// compare numbers down a chain, so (< a b c) means (a < b) and (b < c)
+/// usage: (= <num> <num> ...)
+///        (> <num> <num> ...)
+///        (>= <num> <num> ...)
+///        (< <num> <num> ...)
+///        (<= <num> <num> ...)
+fn compare(op: &str, mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    if args.is_empty() {
+        return procerr!(op, "at least 1 argument required");
+    }
 
-pub fn neq(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
-    logic("!=", args, env)
+    args = eval::eval_list(args, env)?;
+    check_numbers(op, &args)?;
+
+    let holds = args.windows(2).all(|pair| match op {
+        "="  => pair[0] == pair[1],
+        ">"  => pair[0] >  pair[1],
+        ">=" => pair[0] >= pair[1],
+        "<"  => pair[0] <  pair[1],
+        "<=" => pair[0] <= pair[1],
+        _    => panic!("{} is not a valid comparison operator", op),
+    });
+
+    Ok(Bool(holds))
+}
+
+pub fn eq(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    compare("=", args, env)
 }
 
 pub fn gt(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
-    logic(">", args, env)
+    compare(">", args, env)
 }
 
 pub fn geq(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
-    logic(">=", args, env)
+    compare(">=", args, env)
 }
 
 pub fn lt(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
-    logic("<", args, env)
+    compare("<", args, env)
 }
 
 pub fn leq(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
-    logic("<=", args, env)
+    compare("<=", args, env)
+}
+
+pub fn equal(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 2, "equal?")?;
+
+    args = eval::eval_list(args, env)?;
+    Ok(Bool(args[0] == args[1]))
+}
+
+pub fn neq(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 2, "!=")?;
+
+    args = eval::eval_list(args, env)?;
+    Ok(Bool(args[0] != args[1]))
 }
 
 pub fn and(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
-    logic("and", args, env)
+    let mut result = Bool(true);
+
+    for arg in args {
+        result = eval::eval(arg, env.clone())?;
+        if !result.to_bool(env.borrow().truthiness) {
+            break;
+        }
+    }
+
+    Ok(result)
 }
 
 pub fn or(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
-    logic("or", args, env)
+    for arg in args {
+        let result = eval::eval(arg, env.clone())?;
+        if result.to_bool(env.borrow().truthiness) {
+            return Ok(result);
+        }
+    }
+
+    Ok(Bool(false))
 }
 
-/// return the logical inverse of a bool
-/// usage: (not <bool>)
 pub fn not(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 1, "not")?;
 
-    args = eval::eval_list(args, env)?;
-    Ok(Bool(!args[0].to_bool()))
+    args = eval::eval_list(args, env.clone())?;
+    Ok(Bool(!args[0].to_bool(env.borrow().truthiness)))
 }
 // }}}
 
 // {{{ lists
-/// construct a list
-/// usage: (cons <value> <list>)
 pub fn cons(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 2, "cons")?;
 
@@ -427,9 +1180,6 @@ pub fn cons(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     }
 }
 
-/// get the length of a list or a string
-/// usage: (length <list>)
-///        (length <str>)
 pub fn length(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 1, "length")?;
 
@@ -443,8 +1193,6 @@ pub fn length(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     }
 }
 
-/// get the item at an index in a list
-/// usage: (list_ref <list> <ref>)
 pub fn list_ref(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 2, "list-ref")?;
 
@@ -456,8 +1204,6 @@ pub fn list_ref(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
         .ok_or(RunError::IndexOutOfBounds(idx as usize).into())
 }
 
-/// concatenate two lists together
-/// usage: (append <list> <list>)
 pub fn append(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 2, "append")?;
 
@@ -468,8 +1214,6 @@ pub fn append(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     Ok(List(list1))
 }
 
-/// return the first element of a populated list, or nil
-/// usage: (car <list>)
 pub fn car(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 1, "car")?;
 
@@ -478,8 +1222,6 @@ pub fn car(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     Ok(list.get(0).cloned().unwrap_or(Nil))
 }
 
-/// return all elements of a list but the first
-/// usage: (cdr <list>)
 pub fn cdr(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 1, "cdr")?;
 
@@ -488,9 +1230,6 @@ pub fn cdr(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     Ok(List(list.get(1..).unwrap_or(&[]).to_vec()))
 }
 
-/// return a random argument or a random element of a list
-/// usage: (rand <list>)
-///        (rand <expr> <expr> ...)
 pub fn rand(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     if args.is_empty() {
         procerr!("rand", "at least 1 argument required")
@@ -506,15 +1245,11 @@ pub fn rand(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
 // }}}
 
 // {{{ strings
-/// concatenate values together into a string
-/// usage: (cat <value> <value> ...)
 pub fn cat(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     args = eval::eval_list(args, env.clone())?;
     Ok(Str(itertools::join(args, "")))
 }
 
-/// translate the characters in a string to uppercase
-/// usage: (uppercase <str>)
 pub fn uppercase(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 1, "uppercase")?;
     args = eval::eval_list(args, env)?;
@@ -523,8 +1258,6 @@ pub fn uppercase(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     Ok(Str(string.to_uppercase()))
 }
 
-/// translate the characters in a string to lowercase
-/// usage: (lowercase <str>)
 pub fn lowercase(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     check_num_args!(args, 1, "lowercase")?;
     args = eval::eval_list(args, env)?;
@@ -533,3 +1266,180 @@ pub fn lowercase(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     Ok(Str(string.to_lowercase()))
 }
 // }}}
+
+// {{{ ports
+pub fn open_input_file(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 1, "open-input-file")?;
+    open_file(&args[0], "open-input-file", env, |path| ports::Port::open_input_file(path)).map(Port)
+}
+
+pub fn open_output_file(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 1, "open-output-file")?;
+    open_file(&args[0], "open-output-file", env, |path| ports::Port::open_output_file(path)).map(Port)
+}
+
+pub fn call_with_output_file(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 2, "call-with-output-file")?;
+
+    let port = open_file(&args[0], "call-with-output-file", env.clone(), |path| ports::Port::open_output_file(path))?;
+    let func = eval::eval(args[1].clone(), env.clone())?;
+
+    let result = func.call_from(vec![Port(port.clone())], &env);
+    port.close();
+    result
+}
+
+pub fn current_input_port(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 0, "current-input-port")?;
+    let port = env.borrow().ports.input.borrow().clone();
+    Ok(Port(port))
+}
+
+pub fn current_output_port(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 0, "current-output-port")?;
+    let port = env.borrow().ports.output.borrow().clone();
+    Ok(Port(port))
+}
+
+pub fn current_error_port(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 0, "current-error-port")?;
+    let port = env.borrow().ports.error.borrow().clone();
+    Ok(Port(port))
+}
+
+pub fn read_line(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    let port = input_port(args, "read-line", env)?;
+    Ok(port.read_line()?.map_or(Eof, Str))
+}
+
+pub fn read_char(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    let port = input_port(args, "read-char", env)?;
+    Ok(port.read_char()?.map_or(Eof, |c| Str(c.to_string())))
+}
+
+pub fn read(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    let port = input_port(args, "read", env)?;
+    match port.read_datum()? {
+        Some(datum) => Value::new(datum),
+        None => Ok(Eof),
+    }
+}
+
+pub fn write(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    print(args, "write", env, Value::serialize)
+}
+
+pub fn display(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    print(args, "display", env, Value::to_string)
+}
+
+pub fn pp(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    print(args, "pp", env, |value| value.pretty(printer::DEFAULT_WIDTH) + "\n")
+}
+
+pub fn newline(args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    let port = output_port(args, 0, "newline", env)?;
+    port.write_str("\n")?;
+    Ok(Nil)
+}
+
+pub fn close_port(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 1, "close-port")?;
+    args = eval::eval_list(args, env)?;
+
+    let port = extract!(&args[0], &Port, "close-port")?;
+    port.close();
+    Ok(Nil)
+}
+
+pub fn with_output_to_string(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 1, "with-output-to-string")?;
+    args = eval::eval_list(args, env.clone())?;
+
+    let buffer = ports::Port::buffer();
+    let current = env.borrow().ports.clone();
+    let previous = std::mem::replace(&mut *current.output.borrow_mut(), buffer.clone());
+
+    let result = args[0].call_from(Vec::new(), &env);
+    *current.output.borrow_mut() = previous;
+    result?;
+
+    Ok(Str(buffer.contents().unwrap_or_default()))
+}
+
+pub fn eof_object(args: Vec<Value>, _env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 0, "eof-object")?;
+    Ok(Eof)
+}
+
+pub fn is_eof_object(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
+    check_num_args!(args, 1, "eof-object?")?;
+    args = eval::eval_list(args, env)?;
+    Ok(Bool(args[0] == Eof))
+}
+
+fn print(args: Vec<Value>, name: &str, env: EnvRef, show: fn(&Value) -> String) -> Result<Value, Error> {
+    if args.is_empty() {
+        return Err(RunError::WrongNumArgs {
+            name: name.to_string(),
+            expected: 1,
+            got: 0,
+        }.into());
+    }
+
+    let value = eval::eval(args[0].clone(), env.clone())?;
+    let port = output_port(args[1..].to_vec(), 1, name, env)?;
+    port.write_str(&show(&value))?;
+    Ok(Nil)
+}
+
+/// the port in the last of `args`, if there is one, or the current output
+/// port. `before` is how many other arguments there are.
+fn output_port(args: Vec<Value>, before: usize, name: &str, env: EnvRef) -> Result<ports::Port, Error> {
+    let current = env.borrow().ports.output.borrow().clone();
+    optional_port(args, before, name, env, current)
+}
+
+/// the port in `args`, if there is one, or the current input port
+fn input_port(args: Vec<Value>, name: &str, env: EnvRef) -> Result<ports::Port, Error> {
+    let current = env.borrow().ports.input.borrow().clone();
+    optional_port(args, 0, name, env, current)
+}
+
+fn optional_port(
+    mut args: Vec<Value>,
+    before: usize,
+    name: &str,
+    env: EnvRef,
+    current: ports::Port,
+) -> Result<ports::Port, Error> {
+    if args.len() > 1 {
+        return Err(RunError::WrongNumArgs {
+            name: name.to_string(),
+            expected: before + 1,
+            got: before + args.len(),
+        }.into());
+    }
+    args = eval::eval_list(args, env)?;
+
+    match args.pop() {
+        None => Ok(current),
+        Some(port) => extract!(port, Port, name).map_err(Error::from),
+    }
+}
+
+fn open_file(
+    path: &Value,
+    name: &str,
+    env: EnvRef,
+    open: fn(&str) -> Result<ports::Port, Error>,
+) -> Result<ports::Port, Error> {
+    let path = eval::eval(path.clone(), env)?;
+    let path = extract!(&path, &Str, name)?;
+
+    match open(&path) {
+        Ok(port) => Ok(port),
+        Err(err) => procerr!(name, format!("couldn't open {}: {}", path, err)),
+    }
+}
+// }}}
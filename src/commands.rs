@@ -0,0 +1,257 @@
+use failure::Error;
+use itertools::join;
+use rustyline::Editor;
+use std::time::Instant;
+
+use lisprs::Interpreter;
+use lisprs::values::Value;
+
+use crate::Opt;
+use crate::config::Config;
+use crate::helper::LispHelper;
+
+/// everything a meta-command might need to look at
+pub struct Repl<'a> {
+    pub interpreter: &'a Interpreter,
+    pub editor: &'a Editor<LispHelper>,
+    pub opt: &'a Opt,
+    pub config: &'a Config,
+}
+
+/// what the REPL should do after running a command
+pub enum Action {
+    Print(String),
+    /// leave the REPL, with an exit status
+    Quit(i32),
+}
+
+/// the arguments a command takes
+#[derive(Clone, Copy)]
+pub enum Args {
+    /// nothing at all
+    None,
+    /// exactly one word
+    One(&'static str),
+    /// at most one word
+    Optional(&'static str),
+    /// the whole rest of the line, which must not be empty
+    Rest(&'static str),
+}
+
+/// a `>` meta-command in the REPL
+pub struct Command {
+    pub name: &'static str,
+    pub args: Args,
+    pub help: &'static str,
+    run: fn(&Repl, Option<&str>) -> Result<Action, String>,
+}
+
+impl Command {
+    /// how to call the command, e.g. `>load <file>`
+    pub fn usage(&self) -> String {
+        match self.args {
+            Args::None         => format!(">{}", self.name),
+            Args::One(arg)     => format!(">{} <{}>", self.name, arg),
+            Args::Optional(arg) => format!(">{} [{}]", self.name, arg),
+            Args::Rest(arg)    => format!(">{} <{}>", self.name, arg),
+        }
+    }
+
+    /// check the arguments against what the command takes
+    fn parse_args<'l>(&self, args: &'l str) -> Option<Option<&'l str>> {
+        let words = args.split_whitespace().count();
+
+        match self.args {
+            Args::None if words == 0            => Some(None),
+            Args::One(_) if words == 1          => Some(Some(args)),
+            Args::Optional(_) if words == 0     => Some(None),
+            Args::Optional(_) if words == 1     => Some(Some(args)),
+            Args::Rest(_) if words > 0          => Some(Some(args)),
+            _ => None,
+        }
+    }
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command { name: "help",    args: Args::Optional("command"), help: "list the commands, or explain one", run: help },
+    Command { name: "env",     args: Args::None,                help: "list the names defined so far",     run: env },
+    Command { name: "load",    args: Args::One("file"),         help: "run a scheme file",                 run: load },
+    Command { name: "save",    args: Args::Optional("file"),    help: "save definitions to a file (default: the initfile)", run: save },
+    Command { name: "reset",   args: Args::None,                help: "forget everything that has been defined", run: reset },
+    Command { name: "type",    args: Args::Rest("expr"),        help: "show the type of an expression",    run: type_of },
+    Command { name: "time",    args: Args::Rest("expr"),        help: "evaluate an expression and time it", run: time },
+    Command { name: "doc",     args: Args::One("name"),         help: "describe a builtin or definition",  run: doc },
+    Command { name: "undef",   args: Args::One("name"),         help: "remove a definition",               run: undef },
+    Command { name: "history", args: Args::None,                help: "show the input history",            run: history },
+    Command { name: "quit",    args: Args::None,                help: "leave the REPL",                    run: quit },
+];
+
+/// run a line like `>load foo.scm`, with the `>` already stripped off
+pub fn run(repl: &Repl, line: &str) -> Action {
+    let line = line.trim();
+    let (name, args) = match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None    => (line, ""),
+    };
+
+    let command = match COMMANDS.iter().find(|cmd| cmd.name == name) {
+        Some(command) => command,
+        None => {
+            let mut msg = format!("unknown command '>{}'", name);
+            if let Some(suggestion) = closest(name) {
+                msg.push_str(&format!(", did you mean '>{}'?", suggestion));
+            }
+            return Action::Print(msg);
+        }
+    };
+
+    match command.parse_args(args) {
+        Some(args) => (command.run)(repl, args).unwrap_or_else(Action::Print),
+        None       => Action::Print(format!("usage: {}", command.usage())),
+    }
+}
+
+/// find the command name that's the fewest edits away from a misspelling
+fn closest(name: &str) -> Option<&'static str> {
+    COMMANDS.iter()
+        .map(|cmd| (edit_distance(name, cmd.name), cmd.name))
+        .filter(|&(distance, _)| distance <= 2)
+        .min()
+        .map(|(_, name)| name)
+}
+
+/// the levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + if ca == *cb { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+fn help(_repl: &Repl, args: Option<&str>) -> Result<Action, String> {
+    if let Some(name) = args {
+        let name = name.trim_start_matches('>');
+        return match COMMANDS.iter().find(|cmd| cmd.name == name) {
+            Some(cmd) => Ok(Action::Print(format!("{}\n  {}", cmd.usage(), cmd.help))),
+            None      => Err(format!("no such command '>{}'", name)),
+        };
+    }
+
+    let width = COMMANDS.iter().map(|cmd| cmd.usage().len()).max().unwrap_or(0);
+    let lines = COMMANDS.iter()
+        .map(|cmd| format!("{:width$}  {}", cmd.usage(), cmd.help, width = width));
+
+    Ok(Action::Print(join(lines, "\n")))
+}
+
+fn env(repl: &Repl, _args: Option<&str>) -> Result<Action, String> {
+    Ok(Action::Print(join(repl.interpreter.env.borrow().vars.keys(), ", ")))
+}
+
+fn load(repl: &Repl, args: Option<&str>) -> Result<Action, String> {
+    let file = args.unwrap();
+    match repl.interpreter.run_file(file) {
+        Ok(()) => Ok(Action::Print(format!("loaded {}", file))),
+        Err(err) => exit_or(err, |err| format!("couldn't load {}: {}", file, err)),
+    }
+}
+
+fn save(repl: &Repl, args: Option<&str>) -> Result<Action, String> {
+    let file = match (args, &repl.opt.initfile) {
+        (Some(file), _)        => file.into(),
+        (None, Some(initfile)) => initfile.clone(),
+        (None, None)           => return Err("no initfile set, so give a file to save to".to_owned()),
+    };
+
+    repl.interpreter.save_env(&file).map_err(|err| format!("couldn't save {}: {}", file.display(), err))?;
+    Ok(Action::Print(format!("saved to {}", file.display())))
+}
+
+fn reset(repl: &Repl, _args: Option<&str>) -> Result<Action, String> {
+    repl.interpreter.reset();
+    Ok(Action::Print("environment cleared".to_owned()))
+}
+
+fn type_of(repl: &Repl, args: Option<&str>) -> Result<Action, String> {
+    match repl.interpreter.run(args.unwrap()) {
+        Ok(value) => Ok(Action::Print(value.get_type())),
+        Err(err)  => exit_or(err, |err| err.to_string()),
+    }
+}
+
+fn time(repl: &Repl, args: Option<&str>) -> Result<Action, String> {
+    let start = Instant::now();
+    let result = repl.interpreter.run(args.unwrap());
+    let elapsed = start.elapsed();
+
+    let result = match result {
+        Ok(value) => repl.config.show(&value),
+        Err(err)  => match lisprs::exit_status(&err) {
+            Some(status) => return Ok(Action::Quit(status)),
+            None => format!("error: {}", err),
+        },
+    };
+    Ok(Action::Print(format!("{}\n; took {:?}", result, elapsed)))
+}
+
+/// quit if an error came from `(exit)`, like the REPL does, and otherwise
+/// describe it
+fn exit_or(err: Error, describe: impl FnOnce(Error) -> String) -> Result<Action, String> {
+    match lisprs::exit_status(&err) {
+        Some(status) => Ok(Action::Quit(status)),
+        None => Err(describe(err)),
+    }
+}
+
+fn doc(repl: &Repl, args: Option<&str>) -> Result<Action, String> {
+    let name = args.unwrap();
+    if !repl.interpreter.defined_names().iter().any(|defined| defined == name) {
+        return Err(format!("'{}' is not defined", name));
+    }
+
+    let doc = match repl.interpreter.env.borrow().get(name) {
+        Value::Proc(p) => format!("({} {})\n  {}", name, p.params.join(" "), p.body.serialize()),
+        // builtins aren't stored in the Env, so they just resolve to their own name
+        Value::Str(ref s) if s == name => match repl.interpreter.builtin_doc(name) {
+            Some(doc) => doc.to_owned(),
+            None => format!("{}: builtin", name),
+        },
+        value => format!("{}: {} = {}", name, value.get_type(), value.serialize()),
+    };
+
+    Ok(Action::Print(doc))
+}
+
+fn undef(repl: &Repl, args: Option<&str>) -> Result<Action, String> {
+    let name = args.unwrap();
+    if !repl.interpreter.env.borrow().vars.contains_key(name) {
+        return Err(format!("'{}' is not defined", name));
+    }
+
+    repl.interpreter.env.borrow_mut().undefine(name);
+    Ok(Action::Print(format!("undefined {}", name)))
+}
+
+fn history(repl: &Repl, _args: Option<&str>) -> Result<Action, String> {
+    let lines = repl.editor.history()
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| format!("{:>4}  {}", i + 1, entry));
+
+    Ok(Action::Print(join(lines, "\n")))
+}
+
+fn quit(_repl: &Repl, _args: Option<&str>) -> Result<Action, String> {
+    Ok(Action::Quit(0))
+}
@@ -0,0 +1,170 @@
+use rustyline::config::{ColorMode, EditMode};
+use std::fs;
+use std::path::PathBuf;
+
+use lisprs::Interpreter;
+use lisprs::values::Value;
+
+use crate::log;
+
+// the REPL reads its settings from a scheme file, `config.scm` in the lisprs
+// config directory. it's run in an interpreter of its own, and then any of
+// these names that it defined are picked up:
+//
+//   (define prompt "lisprs λ")   ; text shown before each line of input
+//   (define prompt-color 'blue)  ; a color name, or an ANSI code like "1;94"
+//   (define color #t)            ; #f turns off all colors in the REPL
+//   (define edit-mode 'emacs)    ; or 'vi
+//   (define history-size 1000)   ; how many lines of history to keep
+//   (define pretty-print #t)     ; #f prints results on one line, however long
+//   (define print-width 80)      ; how wide results may be before they're broken up
+
+/// settings for the REPL
+#[derive(Debug)]
+pub struct Config {
+    pub prompt: String,
+    pub prompt_color: String,
+    pub color: bool,
+    pub edit_mode: EditMode,
+    pub history_size: usize,
+    pub pretty_print: bool,
+    pub print_width: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            prompt: "lisprs λ".to_owned(),
+            prompt_color: "1;94".to_owned(),
+            color: true,
+            edit_mode: EditMode::Emacs,
+            history_size: 1000,
+            pretty_print: true,
+            print_width: 80,
+        }
+    }
+}
+
+impl Config {
+    /// read the config file, falling back to the defaults for anything it
+    /// doesn't set or sets wrong
+    pub fn load() -> Config {
+        let mut config = Config::default();
+
+        let path = match config_dir() {
+            Some(dir) => dir.join("config.scm"),
+            None      => return config,
+        };
+        let source = match fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(_)     => return config,
+        };
+
+        let settings = Interpreter::default();
+        if let Err(err) = settings.run_source(&source, "config.scm", true) {
+            log::warn(format!("error reading {}: {}", path.display(), err));
+        }
+
+        let vars = settings.env.borrow().vars.clone();
+        for (name, value) in vars {
+            if let Err(msg) = config.set(&name, value) {
+                log::warn(format!("in {}: {}", path.display(), msg));
+            }
+        }
+
+        config
+    }
+
+    /// change one setting
+    fn set(&mut self, name: &str, value: Value) -> Result<(), String> {
+        match (name, value) {
+            ("prompt", Value::Str(prompt)) => self.prompt = prompt,
+
+            ("prompt-color", Value::Str(code)) => self.prompt_color = code,
+            ("prompt-color", Value::Symbol(color)) => {
+                self.prompt_color = color_code(&color)
+                    .ok_or_else(|| format!("unknown color '{}'", color))?
+                    .to_owned();
+            }
+
+            ("color", Value::Bool(color)) => self.color = color,
+
+            ("edit-mode", Value::Symbol(ref mode)) if mode == "emacs" => self.edit_mode = EditMode::Emacs,
+            ("edit-mode", Value::Symbol(ref mode)) if mode == "vi" => self.edit_mode = EditMode::Vi,
+
+            ("history-size", Value::Integer(size)) if size >= 0 => self.history_size = size as usize,
+
+            ("pretty-print", Value::Bool(pretty)) => self.pretty_print = pretty,
+            ("print-width", Value::Integer(width)) if width > 0 => self.print_width = width as usize,
+
+            ("prompt", value) | ("prompt-color", value) | ("color", value)
+            | ("edit-mode", value) | ("history-size", value)
+            | ("pretty-print", value) | ("print-width", value) => {
+                return Err(format!("invalid value {} for {}", value.serialize(), name));
+            }
+
+            // anything else is just a helper definition
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// whether rustyline should highlight input
+    pub fn color_mode(&self) -> ColorMode {
+        if self.color {
+            ColorMode::Enabled
+        } else {
+            ColorMode::Disabled
+        }
+    }
+
+    /// how a result is shown in the REPL
+    pub fn show(&self, value: &Value) -> String {
+        if self.pretty_print {
+            value.pretty(self.print_width)
+        } else {
+            value.serialize()
+        }
+    }
+
+    /// the prompt for a new form
+    pub fn prompt(&self) -> String {
+        self.paint(&self.prompt)
+    }
+
+    fn paint(&self, prompt: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}{} ", self.prompt_color, prompt, log::RESET)
+        } else {
+            format!("{} ", prompt)
+        }
+    }
+}
+
+/// the ANSI code for a named color
+fn color_code(name: &str) -> Option<&'static str> {
+    let code = match name {
+        "black"   => "1;30",
+        "red"     => "1;91",
+        "green"   => "1;92",
+        "yellow"  => "1;93",
+        "blue"    => "1;94",
+        "magenta" => "1;95",
+        "cyan"    => "1;96",
+        "white"   => "1;97",
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+/// where the config file and init.scm live, e.g. ~/.config/lisprs
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("lisprs"))
+}
+
+/// where the history file goes by default, e.g. ~/.local/share/lisprs/history
+pub fn history_file() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("lisprs").join("history"))
+}
@@ -0,0 +1,265 @@
+use failure::Error;
+use num_traits::ToPrimitive;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::errors::RunError;
+use crate::values::Value::{self, *};
+
+// typed conversions between rust and lisprs values, so that rust functions
+// can take and return plain rust types. `name` is the function doing the
+// converting, which is what a TypeError gets blamed on.
+
+/// a rust type that can be made from a lisprs value.
+///
+/// there's no impl for `&str`, only for `String`: the value is handed over,
+/// not lent, so there'd be nothing left for the `&str` to borrow from once
+/// `from_value` returned. `&str` only goes the other way, with `IntoValue`.
+pub trait FromValue: Sized {
+    fn from_value(value: Value, name: &str) -> Result<Self, Error>;
+}
+
+/// a rust type that can be turned into a lisprs value
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+fn type_error<T>(name: &str, expected: &str, got: &Value) -> Result<T, Error> {
+    Err(RunError::TypeError {
+        name: name.to_string(),
+        expected: expected.to_string(),
+        got: got.get_type(),
+    }.into())
+}
+
+// {{{ FromValue
+impl FromValue for Value {
+    fn from_value(value: Value, _name: &str) -> Result<Self, Error> {
+        Ok(value)
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: Value, name: &str) -> Result<Self, Error> {
+        match value {
+            Integer(n) => Ok(n),
+            // a float with no fractional part is close enough
+            Float(n) if value.is_integer() && n.to_i64().is_some() => Ok(n as i64),
+            _ => type_error(name, "Integer", &value),
+        }
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: Value, name: &str) -> Result<Self, Error> {
+        if value.is_number() {
+            Ok(value.to_f64())
+        } else {
+            type_error(name, "Float", &value)
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value, name: &str) -> Result<Self, Error> {
+        match value {
+            Bool(b) => Ok(b),
+            _ => type_error(name, "Bool", &value),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value, name: &str) -> Result<Self, Error> {
+        match value {
+            Str(s) => Ok(s),
+            _ => type_error(name, "Str", &value),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value, name: &str) -> Result<Self, Error> {
+        match value {
+            List(list) => list.into_iter().map(|item| T::from_value(item, name)).collect(),
+            Nil => Ok(Vec::new()),
+            _ => type_error(name, "List", &value),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value, name: &str) -> Result<Self, Error> {
+        match value {
+            Nil => Ok(None),
+            value => T::from_value(value, name).map(Some),
+        }
+    }
+}
+
+/// read from an association list, `((key value) ...)`
+impl<K: FromValue + Eq + Hash, V: FromValue> FromValue for HashMap<K, V> {
+    fn from_value(value: Value, name: &str) -> Result<Self, Error> {
+        Vec::<(K, V)>::from_value(value, name).map(|pairs| pairs.into_iter().collect())
+    }
+}
+
+macro_rules! tuple_from_value {
+    ($len: expr, $($t: ident),+) => {
+        impl<$($t: FromValue),+> FromValue for ($($t,)+) {
+            fn from_value(value: Value, name: &str) -> Result<Self, Error> {
+                match value {
+                    List(list) if list.len() == $len => {
+                        let mut items = list.into_iter();
+                        Ok(($($t::from_value(items.next().unwrap(), name)?,)+))
+                    }
+                    _ => type_error(name, &format!("List of {}", $len), &value),
+                }
+            }
+        }
+    }
+}
+
+tuple_from_value!(1, A);
+tuple_from_value!(2, A, B);
+tuple_from_value!(3, A, B, C);
+tuple_from_value!(4, A, B, C, D);
+// }}}
+
+// {{{ IntoValue
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Nil
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        Integer(self)
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Float(self)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Bool(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Str(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Str(self.to_owned())
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        List(self.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        match self {
+            Some(value) => value.into_value(),
+            None => Nil,
+        }
+    }
+}
+
+/// written as an association list, `((key value) ...)`
+impl<K: IntoValue, V: IntoValue> IntoValue for HashMap<K, V> {
+    fn into_value(self) -> Value {
+        List(self.into_iter().map(|pair| pair.into_value()).collect())
+    }
+}
+
+macro_rules! tuple_into_value {
+    ($($t: ident $i: tt),+) => {
+        impl<$($t: IntoValue),+> IntoValue for ($($t,)+) {
+            fn into_value(self) -> Value {
+                List(vec![$(self.$i.into_value()),+])
+            }
+        }
+    }
+}
+
+tuple_into_value!(A 0);
+tuple_into_value!(A 0, B 1);
+tuple_into_value!(A 0, B 1, C 2);
+tuple_into_value!(A 0, B 1, C 2, D 3);
+// }}}
+
+// {{{ typed rust functions
+/// what a rust function registered with `Interpreter::register_fn` can return:
+/// anything that turns into a value, or a `Result` of one
+pub trait IntoResult {
+    fn into_result(self) -> Result<Value, Error>;
+}
+
+impl<T: IntoValue> IntoResult for T {
+    fn into_result(self) -> Result<Value, Error> {
+        Ok(self.into_value())
+    }
+}
+
+impl<T: IntoValue> IntoResult for Result<T, Error> {
+    fn into_result(self) -> Result<Value, Error> {
+        self.map(IntoValue::into_value)
+    }
+}
+
+/// a rust function with typed arguments, which can be called with a list of
+/// values. `Args` is the tuple of argument types.
+pub trait NativeFunction<Args> {
+    fn call_with(&self, name: &str, args: Vec<Value>) -> Result<Value, Error>;
+}
+
+macro_rules! native_function {
+    ($len: expr $(, $t: ident)*) => {
+        impl<Func, Ret, $($t),*> NativeFunction<($($t,)*)> for Func
+        where
+            Func: Fn($($t),*) -> Ret,
+            Ret: IntoResult,
+            $($t: FromValue,)*
+        {
+            #[allow(unused_mut, unused_variables)]
+            fn call_with(&self, name: &str, args: Vec<Value>) -> Result<Value, Error> {
+                if args.len() != $len {
+                    return Err(RunError::WrongNumArgs {
+                        name: name.to_string(),
+                        expected: $len,
+                        got: args.len(),
+                    }.into());
+                }
+
+                let mut args = args.into_iter();
+                self($($t::from_value(args.next().unwrap(), name)?),*).into_result()
+            }
+        }
+    }
+}
+
+native_function!(0);
+native_function!(1, A);
+native_function!(2, A, B);
+native_function!(3, A, B, C);
+native_function!(4, A, B, C, D);
+// }}}
@@ -1,52 +1,160 @@
-use std::cell::RefCell;
 use std::collections::HashMap;
-use std::rc::Rc;
 
-use crate::values::Value;
+use failure::Error;
+
+use crate::errors::RunError;
+use crate::limits::Budget;
+use crate::modules::Modules;
+use crate::ports::CurrentPorts;
+use crate::sandbox::Capabilities;
+use crate::shared::{Lock, Shared};
+use crate::values::{Truthiness, Value};
 
 /// The “memory” of the interpreter is represented as a HashMap, with an
-/// optional parent EnvRef, that is passed around in an Rc<Refcell<>>,
-/// which allows for multiple “owners” with interior mutability.
+/// optional parent EnvRef, that is passed around in an Rc<RefCell<>>
+/// (or an Arc<RwLock<>>, with the `sync` feature), which allows for
+/// multiple “owners” with interior mutability.
 #[derive(Debug, Clone)]
 pub struct Env {
     pub vars: HashMap<String, Value>,
     pub parent: Option<EnvRef>,
+    /// which values count as false in conditions; inherited from the parent
+    pub truthiness: Truthiness,
+    /// which builtins may be called; inherited from the parent
+    pub capabilities: Shared<Capabilities>,
+    /// how much more work may be done; shared with the parent
+    pub budget: Shared<Budget>,
+    /// the libraries that can be imported; shared with the parent
+    pub modules: Shared<Modules>,
+    /// where input and output go by default; shared with the parent
+    pub ports: Shared<CurrentPorts>,
+    /// which library each imported var came from
+    pub imported: HashMap<String, String>,
+    /// whether this is the root of a `FrozenEnv`, which nothing changes
+    pub frozen: bool,
 }
 
 /// an interior-mutable, reference-counted smart pointer wrapper around an `Env`
-pub type EnvRef = Rc<RefCell<Env>>;
+pub type EnvRef = Shared<Lock<Env>>;
 
 impl Env {
     /// create a new lisprs environment
     pub fn new(parent: Option<EnvRef>) -> Env {
+        let (truthiness, capabilities, budget, modules, ports) = match &parent {
+            Some(env) => {
+                let env = env.borrow();
+                (
+                    env.truthiness,
+                    env.capabilities.clone(),
+                    env.budget.clone(),
+                    env.modules.clone(),
+                    env.ports.clone(),
+                )
+            }
+            None => (
+                Truthiness::default(),
+                Shared::new(Capabilities::default()),
+                Shared::new(Budget::default()),
+                Shared::new(Modules::default()),
+                Shared::new(CurrentPorts::default()),
+            ),
+        };
+
         Env {
             vars: HashMap::new(),
             parent,
+            truthiness,
+            capabilities,
+            budget,
+            modules,
+            ports,
+            imported: HashMap::new(),
+            frozen: false,
         }
     }
 
+    /// take on another Env's truthiness, capabilities, budget, libraries
+    /// and ports
+    pub fn inherit_settings(&mut self, other: &Env) {
+        self.truthiness = other.truthiness;
+        self.capabilities = other.capabilities.clone();
+        self.budget = other.budget.clone();
+        self.modules = other.modules.clone();
+        self.ports = other.ports.clone();
+    }
+
     /// resolve a symbol to a stored lisprs value, returning
     /// itself as a string if no stored value is found
     pub fn get(&self, var_name: &str) -> Value {
+        self.lookup(var_name).unwrap_or_else(|| Value::Str(var_name.to_owned()))
+    }
+
+    /// find a stored lisprs value, here or in a parent
+    pub fn lookup(&self, var_name: &str) -> Option<Value> {
         match self.vars.get(var_name) {
-            Some(x) => x.clone(),
+            Some(x) => Some(x.clone()),
             None => {
                 // try to find the var in the parent
                 match &self.parent {
-                    Some(env) => env.borrow().get(var_name),
-                    None      => Value::Str(var_name.to_owned()),
+                    Some(env) => env.borrow().lookup(var_name),
+                    None      => None,
                 }
             }
         }
     }
 
-    /// add (or modify) a stored value in the environment
-    pub fn define(&mut self, var_name: &str, value: Value) {
+    /// add (or modify) a stored value in the environment. an imported name
+    /// can't be redefined, the same way an import can't replace a definition
+    /// (see `check_import`), until it's been undefined.
+    pub fn define(&mut self, var_name: &str, value: Value) -> Result<(), Error> {
+        if let Some(library) = self.imported.get(var_name) {
+            return Err(RunError::Imported {
+                name: var_name.to_owned(),
+                library: library.clone(),
+            }.into());
+        }
+
+        self.vars.insert(var_name.to_owned(), value);
+        Ok(())
+    }
+
+    /// add (or modify) a stored value, replacing an import of the same name
+    pub fn redefine(&mut self, var_name: &str, value: Value) {
+        self.undefine(var_name);
         self.vars.insert(var_name.to_owned(), value);
     }
 
+    /// add a value that `library` exports. it's an error if something else
+    /// already has the same name, see `check_import`.
+    pub fn import(&mut self, var_name: &str, value: Value, library: &str) -> Result<(), Error> {
+        self.check_import(var_name, library)?;
+        self.vars.insert(var_name.to_owned(), value);
+        self.imported.insert(var_name.to_owned(), library.to_owned());
+        Ok(())
+    }
+
+    /// make sure `library` can import `var_name` here: nothing but an earlier
+    /// import from the same library may have the name already, neither
+    /// another library's import nor a definition
+    pub fn check_import(&self, var_name: &str, library: &str) -> Result<(), Error> {
+        match self.imported.get(var_name) {
+            Some(other) if other == library => Ok(()),
+            Some(other) => Err(RunError::NameCollision {
+                name: var_name.to_owned(),
+                first: other.clone(),
+                second: library.to_owned(),
+            }.into()),
+            None if self.vars.contains_key(var_name) => Err(RunError::AlreadyDefined {
+                name: var_name.to_owned(),
+                library: library.to_owned(),
+            }.into()),
+            None => Ok(()),
+        }
+    }
+
     /// remove a stored value from the environment
     pub fn undefine(&mut self, var_name: &str) {
         self.vars.remove(var_name);
+        self.imported.remove(var_name);
     }
 }
@@ -1,5 +1,7 @@
 use failure::Fail;
 
+use crate::sandbox::Capability;
+
 #[derive(Debug, Fail)]
 pub enum ParseError {
     #[fail(display = "empty expression")]
@@ -10,6 +12,9 @@ pub enum ParseError {
 
     #[fail(display = "encountered erroneous '{}'", _0)]
     ErroneousToken(String),
+
+    #[fail(display = "invalid escape sequence '{}' in string", _0)]
+    InvalidEscape(String),
 }
 
 #[derive(Debug, Fail)]
@@ -37,6 +42,60 @@ pub enum RunError {
         got: usize,
     },
 
+    #[fail(display = "{}: capability not granted ({:?})", name, capability)]
+    NotGranted { name: String, capability: Capability },
+
+    #[fail(display = "{} limit of {} exceeded", limit, max)]
+    LimitExceeded { limit: String, max: u64 },
+
+    #[fail(display = "interrupted")]
+    Interrupted,
+
+    #[fail(display = "{} is not defined", _0)]
+    Undefined(String),
+
     #[fail(display = "division by zero is undefined")]
     DivideByZero,
+
+    #[fail(display = "{} has no exact representation", _0)]
+    NoExactRepresentation(String),
+
+    #[fail(display = "{} is imported from both {} and {}", name, first, second)]
+    NameCollision {
+        name: String,
+        first: String,
+        second: String,
+    },
+
+    #[fail(display = "{} is already defined, so it can't be imported from {}", name, library)]
+    AlreadyDefined {
+        name: String,
+        library: String,
+    },
+
+    #[fail(display = "{} is imported from {}, so it can't be redefined", name, library)]
+    Imported {
+        name: String,
+        library: String,
+    },
+
+    #[fail(display = "no library {} in the load path", _0)]
+    LibraryNotFound(String),
+
+    #[fail(display = "{} is already being loaded", _0)]
+    CircularLoad(String),
+
+    #[fail(display = "invalid image: {}", _0)]
+    BadImage(String),
+
+    #[fail(display = "exit with status {}", _0)]
+    Exit(i32),
+
+    #[fail(display = "{} error in {}:{}:\n  {}", kind, file, line, msg)]
+    InFile {
+        kind: String,
+        file: String,
+        line: usize,
+        msg: String,
+    },
 }
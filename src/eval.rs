@@ -13,6 +13,9 @@ pub fn eval(s_exp: Value, env: EnvRef) -> Result<Value, Error> {
     // log::debug(format!("{:?}", s_exp));
     // log::debug(format!("{}", s_exp.to_string()));
 
+    let budget = env.borrow().budget.clone();
+    let _depth = budget.enter()?;
+
     match s_exp {
         Symbol(ref sym) => {
             if sym.starts_with('\'') {
@@ -26,7 +29,9 @@ pub fn eval(s_exp: Value, env: EnvRef) -> Result<Value, Error> {
             if list.is_empty() {
                 Ok(Nil)
             } else {
-                run_proc(list, env)
+                let result = run_proc(list, env)?;
+                budget.check_size(&result)?;
+                Ok(result)
             }
         }
 
@@ -58,21 +63,37 @@ fn run_proc(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
     match first_element {
         Symbol(s) => {
             // check to see if it's a builtin function
-            for (name, func) in BUILTINS {
-                if &s == name {
-                    return func(args, env);
+            for builtin in BUILTINS {
+                if s == builtin.name {
+                    if !env.borrow().capabilities.allows(builtin.name, builtin.capability) {
+                        return Err(RunError::NotGranted {
+                            name: s,
+                            capability: builtin.capability,
+                        }.into());
+                    }
+
+                    return (builtin.run)(args, env);
                 }
             }
 
             let first_value = resolve_symbol(&s, env.clone());
-            if let Proc(procedure) = first_value {
-                args = eval_list(args, env.clone())?;
-                return procedure.call(s, args);
-            } else {
-                return Err(RunError::UncallableValue {
-                    name: s,
-                    typename: first_value.get_type(),
-                }.into());
+            match first_value {
+                Proc(procedure) => {
+                    args = eval_list(args, env.clone())?;
+                    return procedure.call_from(s, args, &env);
+                }
+
+                Native(f) => {
+                    args = eval_list(args, env.clone())?;
+                    return f.call(args);
+                }
+
+                _ => {
+                    return Err(RunError::UncallableValue {
+                        name: s,
+                        typename: first_value.get_type(),
+                    }.into());
+                }
             }
         }
 
@@ -84,7 +105,12 @@ fn run_proc(mut args: Vec<Value>, env: EnvRef) -> Result<Value, Error> {
 
         Proc(p) => {
             args = eval_list(args, env.clone())?;
-            return p.call("<anonymous procedure>".to_owned(), args);
+            return p.call_from("<anonymous procedure>".to_owned(), args, &env);
+        }
+
+        Native(f) => {
+            args = eval_list(args, env.clone())?;
+            return f.call(args);
         }
 
         _ => {
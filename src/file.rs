@@ -1,53 +1,100 @@
 use failure::Error;
 
-use std::ffi::OsStr;
 use std::fmt::Debug;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::prelude::*;
-use std::io::{BufReader, BufWriter};
+use std::io::BufWriter;
 use std::path::Path;
 
-use crate::errors::ParseError;
-use crate::Interpreter;
+use crate::errors::{ParseError, RunError};
+use crate::parser::is_incomplete;
+use crate::printer;
+use crate::values::Value::{List, Symbol};
+use crate::{exit_status, Interpreter};
 use crate::log;
 
 impl Interpreter {
-    /// run each line of a file
+    /// run each form in a file, warning about any that fail
     pub fn run_file<P>(&self, path: P) -> Result<(), Error>
     where
         P: AsRef<Path> + Debug,
     {
-        let file = File::open(&path)?;
-        let buf = BufReader::new(file);
-        let mut lines = buf.lines().enumerate();
+        let source = fs::read_to_string(&path)?;
+        self.run_source(&source, &path.as_ref().to_string_lossy(), true)
+    }
+
+    /// run each form in some source code, which may span several lines.
+    /// a `#!` line at the very start is skipped. with `keep_going`, errors
+    /// are logged and the rest of the code still runs; otherwise the first
+    /// error is returned. `load` and `include` in the code look for files
+    /// relative to `filename`.
+    pub fn run_source(&self, source: &str, filename: &str, keep_going: bool) -> Result<(), Error> {
+        let modules = self.env.borrow().modules.clone();
+        modules.enter_file(Path::new(filename))?;
+        let result = self.run_forms(source, filename, keep_going);
+        modules.leave_file();
+        result
+    }
+
+    fn run_forms(&self, source: &str, filename: &str, keep_going: bool) -> Result<(), Error> {
+        let report = |kind: &str, line: usize, msg: String| -> Result<(), Error> {
+            let err = RunError::InFile {
+                kind: kind.to_owned(),
+                file: filename.to_owned(),
+                line,
+                msg,
+            };
+
+            if keep_going {
+                log::warn(err);
+                Ok(())
+            } else {
+                Err(err.into())
+            }
+        };
+
+        let mut form = String::new();
+        let mut start = 0;
 
-        let filename = path.as_ref()
-            .file_name()
-            .unwrap_or_else(|| OsStr::new("<unknown>"))
-            .to_string_lossy();
+        for (linenum, line) in source.lines().enumerate() {
+            if linenum == 0 && line.starts_with("#!") {
+                continue;
+            }
+
+            if form.trim().is_empty() {
+                form.clear();
+                start = linenum;
+            } else {
+                form.push('\n');
+            }
+            form.push_str(line);
+
+            if is_incomplete(&form) {
+                continue;
+            }
+
+            let result = self.run(form.as_str());
+            form.clear();
+
+            if let Err(err) = result {
+                // (exit) always stops everything, even when keeping going
+                if exit_status(&err).is_some() {
+                    return Err(err);
+                }
 
-        while let Some((linenum, Ok(line))) = lines.next() {
-            if let Err(err) = self.run(line.as_str()) {
                 match err.downcast::<ParseError>() {
                     Ok(ParseError::Empty) => continue,
-
-                    Ok(err) => log::warn(format!(
-                        "parsing error in {}:{}:\n  {}",
-                        filename,
-                        linenum + 1,
-                        err
-                    )),
-
-                    Err(err) => log::warn(format!(
-                        "runtime error in {}:{}:\n  {}",
-                        filename,
-                        linenum + 1,
-                        err
-                    )),
+                    Ok(err)  => report("parsing", start + 1, err.to_string())?,
+                    Err(err) => report("runtime", start + 1, err.to_string())?,
                 }
             }
         }
 
+        // whatever is left over never got finished
+        if !form.trim().is_empty() {
+            report("parsing", start + 1, ParseError::MismatchedParens.to_string())?;
+        }
+
         Ok(())
     }
 
@@ -62,7 +109,13 @@ impl Interpreter {
 
         let env = self.env.clone();
         for (key, value) in &env.borrow().vars {
-            writeln!(&mut buf, "(define {} {})", key, value.serialize())?;
+            let define = List(vec![Symbol("define".to_owned()), Symbol(key.clone()), value.clone()]);
+            // it wouldn't read back in as the same thing
+            if printer::too_deep(&define) {
+                log::warn(format!("not saving {}, which is nested too deeply to print", key));
+                continue;
+            }
+            writeln!(&mut buf, "{}", define.pretty(printer::DEFAULT_WIDTH))?;
         }
 
         Ok(())
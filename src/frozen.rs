@@ -0,0 +1,41 @@
+use crate::env::*;
+use crate::shared::{Lock, Shared};
+use crate::Interpreter;
+
+/// a set of definitions that can't be changed any more, which any number of
+/// interpreters can start from without copying it. with the `sync` feature,
+/// it can be shared between threads, e.g. a prelude that's loaded once and
+/// then used by a pool of workers, each with an interpreter of its own.
+///
+/// anything an interpreter defines goes into its own Env, on top of the
+/// frozen one, so the interpreters don't see each other's definitions.
+#[derive(Debug, Clone)]
+pub struct FrozenEnv {
+    env: EnvRef,
+}
+
+impl FrozenEnv {
+    /// a new interpreter that starts out with everything in this env, and
+    /// the same settings it was frozen with
+    pub fn interpreter(&self) -> Interpreter {
+        let mut env = Env::new(Some(self.env.clone()));
+        // every interpreter counts its own steps, is interrupted on its own,
+        // and can be given ports, libraries and a load path of its own
+        env.budget = Shared::default();
+        env.budget.set_limits(self.env.borrow().budget.limits());
+        env.ports = Shared::new(self.env.borrow().ports.copy());
+        env.modules = Shared::new(self.env.borrow().modules.copy());
+
+        Interpreter { env: Shared::new(Lock::new(env)) }
+    }
+}
+
+impl Interpreter {
+    /// stop changing this interpreter's definitions, so that other
+    /// interpreters can start from them. any clones of this interpreter
+    /// shouldn't be used to define anything after this. see `FrozenEnv`.
+    pub fn freeze(self) -> FrozenEnv {
+        self.env.borrow_mut().frozen = true;
+        FrozenEnv { env: self.env }
+    }
+}
@@ -0,0 +1,236 @@
+use rustyline::completion::Completer;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Helper};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use lisprs::Interpreter;
+use lisprs::values::Value;
+
+use crate::log;
+
+const STRING_COLOR: &str = log::GRN;
+const NUMBER_COLOR: &str = log::YEL;
+const COMMENT_COLOR: &str = log::GRY;
+const KEYWORD_COLOR: &str = "\x1B[1;35m";
+const MATCHING_PAREN: &str = "\x1B[1;4;96m";
+
+/// special forms, which get highlighted differently from plain procedures
+const KEYWORDS: &[&str] = &[
+    "define", "undef", "lambda", "let", "if", "cond", "else", "quote", "and", "or",
+];
+
+/// characters that separate one word from the next
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || c == '(' || c == ')' || c == '\'' || c == '"'
+}
+
+/// a rustyline helper that completes names, highlights syntax and matching
+/// parens, and asks for more input while a form is unfinished
+pub struct LispHelper {
+    interpreter: Interpreter,
+    commands: Vec<String>,
+}
+
+impl LispHelper {
+    pub fn new(interpreter: Interpreter, commands: &[&str]) -> LispHelper {
+        LispHelper {
+            interpreter,
+            commands: commands.iter().map(|cmd| format!(">{}", cmd)).collect(),
+        }
+    }
+}
+
+impl Completer for LispHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context) -> rustyline::Result<(usize, Vec<String>)> {
+        // meta-commands only make sense at the very start of the line
+        if line.starts_with('>') && !line[..pos].contains(' ') {
+            let candidates = self.commands.iter()
+                .filter(|cmd| cmd.starts_with(&line[..pos]))
+                .cloned()
+                .collect();
+            return Ok((0, candidates));
+        }
+
+        let start = line[..pos]
+            .rfind(is_delimiter)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let prefix = &line[start..pos];
+
+        let candidates = self.interpreter.defined_names()
+            .into_iter()
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for LispHelper {
+    type Hint = String;
+}
+
+impl Highlighter for LispHelper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if line.starts_with('>') {
+            return Cow::Borrowed(line);
+        }
+
+        Cow::Owned(highlight(line, pos))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        // redraw whenever the cursor moves, so the paren matching follows it
+        true
+    }
+}
+
+impl Validator for LispHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let input = ctx.input();
+
+        if !input.starts_with('>') && lisprs::is_incomplete(input) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Helper for LispHelper {}
+
+/// find the byte offsets of every pair of matching parens, in both directions,
+/// skipping over any that are in strings or comments
+fn matching_parens(line: &str) -> HashMap<usize, usize> {
+    let mut pairs = HashMap::new();
+    let mut open = Vec::new();
+
+    let mut escaped_state = false;
+    let mut string_state = false;
+    let mut comment_state = false;
+
+    for (i, c) in line.char_indices() {
+        if comment_state {
+            comment_state = c != '\n';
+        } else if string_state {
+            if escaped_state {
+                escaped_state = false;
+            } else if c == '\\' {
+                escaped_state = true;
+            } else if c == '"' {
+                string_state = false;
+            }
+        } else {
+            match c {
+                '(' => open.push(i),
+                ')' => {
+                    if let Some(j) = open.pop() {
+                        pairs.insert(i, j);
+                        pairs.insert(j, i);
+                    }
+                }
+                '"' => string_state = true,
+                ';' => comment_state = true,
+                _ => (),
+            }
+        }
+    }
+
+    pairs
+}
+
+/// color a word depending on whether it's a keyword or a literal
+fn highlight_word(word: &str, out: &mut String) {
+    let color = if KEYWORDS.contains(&word) {
+        Some(KEYWORD_COLOR)
+    } else {
+        match Value::new(word.to_owned()) {
+            Ok(Value::Bool(_)) => Some(NUMBER_COLOR),
+            Ok(ref n) if n.is_number() => Some(NUMBER_COLOR),
+            _ => None,
+        }
+    };
+
+    match color {
+        Some(color) => out.push_str(&format!("{}{}{}", color, word, log::RESET)),
+        None => out.push_str(word),
+    }
+}
+
+/// add terminal colors to a line of code
+fn highlight(line: &str, pos: usize) -> String {
+    let pairs = matching_parens(line);
+
+    // the paren under the cursor, or the one just before it
+    let cursor_paren = [Some(pos), pos.checked_sub(1)].iter()
+        .filter_map(|&i| i)
+        .find(|i| pairs.contains_key(i));
+    let highlighted_parens: Vec<usize> = match cursor_paren {
+        Some(i) => vec![i, pairs[&i]],
+        None    => vec![],
+    };
+
+    let mut out = String::with_capacity(line.len() * 2);
+    let mut word = String::new();
+
+    let mut escaped_state = false;
+    let mut string_state = false;
+    let mut comment_state = false;
+
+    for (i, c) in line.char_indices() {
+        if comment_state {
+            if c == '\n' {
+                comment_state = false;
+                out.push_str(log::RESET);
+            }
+            out.push(c);
+        } else if string_state {
+            out.push(c);
+            if escaped_state {
+                escaped_state = false;
+            } else if c == '\\' {
+                escaped_state = true;
+            } else if c == '"' {
+                string_state = false;
+                out.push_str(log::RESET);
+            }
+        } else if is_delimiter(c) || c == ';' {
+            highlight_word(&word, &mut out);
+            word.clear();
+
+            match c {
+                '"' => {
+                    string_state = true;
+                    out.push_str(STRING_COLOR);
+                    out.push(c);
+                }
+
+                ';' => {
+                    comment_state = true;
+                    out.push_str(COMMENT_COLOR);
+                    out.push(c);
+                }
+
+                '(' | ')' if highlighted_parens.contains(&i) => {
+                    out.push_str(&format!("{}{}{}", MATCHING_PAREN, c, log::RESET));
+                }
+
+                _ => out.push(c),
+            }
+        } else {
+            word.push(c);
+        }
+    }
+
+    highlight_word(&word, &mut out);
+    if string_state || comment_state {
+        out.push_str(log::RESET);
+    }
+
+    out
+}
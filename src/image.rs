@@ -0,0 +1,368 @@
+use failure::Error;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs;
+use std::path::Path;
+
+use crate::env::*;
+use crate::errors::RunError;
+use crate::parser;
+use crate::shared::{Lock, Shared};
+use crate::values::{LispProc, Truthiness, Value::{self, *}};
+use crate::Interpreter;
+
+// an image is a snapshot of the whole environment graph: the global Env,
+// and every Env that a closure has captured, along with their parents.
+// each Env gets a number, so closures that share an Env still share it
+// after loading, and closures can refer to the Env they're defined in.
+//
+// the image itself is one big s-expression:
+//
+//   (lisprs-image 1
+//     (env 0 nil scheme
+//       (x 5)
+//       (add (proc 1 (a b) (list + a b))))
+//     (env 1 0 scheme
+//       (y "captured")))
+//
+// an env is `(env <id> <parent id or nil> <truthiness> (<name> <value>)...)`.
+// native functions and ports aren't saved, and neither is a `FrozenEnv` the
+// interpreter was made from: envs and closures that refer to it say `frozen`
+// instead of an id, and are put back on top of whichever one the image is
+// loaded into.
+// atoms are written as they are, but lists are tagged so they can't be
+// confused with the other tags:
+//
+//   (list <value>...)                     a list
+//   (proc <env id> (<param>...) <body>)   a closure
+//   (symbol "<name>")                     a symbol that can't be written bare
+
+const VERSION: i64 = 1;
+
+impl Interpreter {
+    /// save a snapshot of everything that's been defined, closures and all
+    pub fn save_image<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path> + Debug,
+    {
+        fs::write(path, dump(&self.env))?;
+        Ok(())
+    }
+
+    /// replace everything that's been defined with a snapshot from `save_image`
+    pub fn load_image<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path> + Debug,
+    {
+        restore(&fs::read_to_string(path)?, &self.env)
+    }
+}
+
+/// the outermost Env, which holds the global definitions. a `FrozenEnv`
+/// underneath them doesn't count, since it can't be changed.
+pub fn global_env(env: &EnvRef) -> EnvRef {
+    let mut env = env.clone();
+    loop {
+        let parent = env.borrow().parent.clone();
+        match parent {
+            Some(parent) if !parent.borrow().frozen => env = parent,
+            _ => return env,
+        }
+    }
+}
+
+// {{{ saving
+/// write out an image of `root` and everything reachable from it
+pub fn dump(root: &EnvRef) -> String {
+    let mut writer = Writer {
+        ids: HashMap::new(),
+        queue: Vec::new(),
+    };
+    writer.id(root);
+
+    let mut out = format!("; vim: set ft=scheme:\n(lisprs-image {}", VERSION);
+
+    // writing an env can find more envs, so keep going until there are none left
+    let mut i = 0;
+    while i < writer.queue.len() {
+        let env = writer.queue[i].clone();
+        out.push_str(&writer.env(&env));
+        i += 1;
+    }
+
+    out.push_str(")\n");
+    out
+}
+
+struct Writer {
+    /// the id for each Env, by address
+    ids: HashMap<*const Lock<Env>, usize>,
+    /// every Env found so far, in id order
+    queue: Vec<EnvRef>,
+}
+
+impl Writer {
+    /// number an Env, the first time it's seen
+    fn id(&mut self, env: &EnvRef) -> usize {
+        let queue = &mut self.queue;
+        *self.ids.entry(Shared::as_ptr(env)).or_insert_with(|| {
+            queue.push(env.clone());
+            queue.len() - 1
+        })
+    }
+
+    /// what to call an Env where another one refers to it
+    fn reference(&mut self, env: &EnvRef) -> String {
+        if env.borrow().frozen {
+            "frozen".to_owned()
+        } else {
+            self.id(env).to_string()
+        }
+    }
+
+    fn env(&mut self, env: &EnvRef) -> String {
+        let env_id = self.id(env);
+        let env = env.borrow();
+
+        let parent = match &env.parent {
+            Some(parent) => self.reference(parent),
+            None => "nil".to_owned(),
+        };
+        let truthiness = match env.truthiness {
+            Truthiness::Scheme => "scheme",
+            Truthiness::Legacy => "legacy",
+        };
+
+        let mut out = format!("\n  (env {} {} {}", env_id, parent, truthiness);
+
+        // sorted, so that saving the same thing twice gives the same file.
+        // native functions belong to the host program, which registers them
+        // again itself, and open files can't be saved, so they're left out
+        let mut names: Vec<&String> = env.vars.iter()
+            .filter(|(_, value)| !matches!(value, Native(_) | Port(_) | Eof))
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+
+        for name in names {
+            let value = self.value(&env.vars[name]);
+            out.push_str(&format!("\n    ({} {})", symbol(name), value));
+        }
+
+        out.push(')');
+        out
+    }
+
+    fn value(&mut self, value: &Value) -> String {
+        match value {
+            Symbol(s) => symbol(s),
+            Float(n)  => format!("{:?}", n),
+            Native(_) | Port(_) | Eof => "nil".to_owned(),
+            List(list) => {
+                let items: Vec<String> = list.iter().map(|item| self.value(item)).collect();
+                format!("(list{}{})", if items.is_empty() { "" } else { " " }, items.join(" "))
+            }
+            Proc(p) => format!(
+                "(proc {} ({}) {})",
+                self.reference(&p.env),
+                p.params.iter().map(|param| symbol(param)).collect::<Vec<_>>().join(" "),
+                self.value(&p.body)
+            ),
+            atom => atom.serialize(),
+        }
+    }
+}
+
+/// write a symbol bare if it reads back as itself, and tagged otherwise
+fn symbol(name: &str) -> String {
+    match Value::new(name.to_owned()) {
+        Ok(Symbol(ref s)) if s == name => name.to_owned(),
+        _ => format!("(symbol \"{}\")", parser::escape(name)),
+    }
+}
+// }}}
+
+// {{{ loading
+/// read an image back in, replacing everything in `root`
+pub fn restore(image: &str, root: &EnvRef) -> Result<(), Error> {
+    let mut items = match Value::new(image.to_owned())? {
+        List(items) => items.into_iter(),
+        _ => return bad_image("not an image"),
+    };
+
+    match (items.next(), items.next()) {
+        (Some(Symbol(ref tag)), Some(Integer(VERSION))) if tag == "lisprs-image" => (),
+        (Some(Symbol(ref tag)), Some(version)) if tag == "lisprs-image" => {
+            return bad_image(format!("unsupported version {}", version));
+        }
+        _ => return bad_image("not an image"),
+    }
+
+    // read all the envs' headers first, so that anything can refer to any env
+    let mut envs = Vec::new();
+    for env in items {
+        match env {
+            List(env) => envs.push(env),
+            _ => return bad_image("expected an env"),
+        }
+    }
+
+    let mut refs = Refs {
+        ids: HashMap::new(),
+        frozen: global_env(root).borrow().parent.clone(),
+    };
+    for env in &envs {
+        let id = match env.get(1) {
+            Some(Integer(id)) => *id,
+            _ => return bad_image("env without an id"),
+        };
+
+        // env 0 is the root, which gets loaded into the existing env in place
+        let env_ref = if id == 0 {
+            root.clone()
+        } else {
+            let mut env = Env::new(None);
+            env.inherit_settings(&root.borrow());
+            Shared::new(Lock::new(env))
+        };
+
+        if refs.ids.insert(id, env_ref).is_some() {
+            return bad_image(format!("env {} is defined twice", id));
+        }
+    }
+
+    if !refs.ids.contains_key(&0) {
+        return bad_image("no root env");
+    }
+
+    let mut loaded = Vec::new();
+    for env in envs {
+        loaded.push(read_env(env, &refs)?);
+    }
+
+    // only touch anything once the whole image has been read successfully,
+    // and keep any native functions the host registered
+    for (env_ref, mut env) in loaded {
+        for (name, value) in &env_ref.borrow().vars {
+            if let Native(_) = value {
+                env.vars.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        *env_ref.borrow_mut() = env;
+    }
+
+    Ok(())
+}
+
+/// the envs an image can refer to: its own, by id, and the frozen one
+/// underneath the interpreter it's loaded into, if there is one
+struct Refs {
+    ids: HashMap<i64, EnvRef>,
+    frozen: Option<EnvRef>,
+}
+
+fn read_env(env: Vec<Value>, refs: &Refs) -> Result<(EnvRef, Env), Error> {
+    let mut items = env.into_iter();
+
+    match items.next() {
+        Some(Symbol(ref tag)) if tag == "env" => (),
+        _ => return bad_image("expected an env"),
+    }
+
+    let env_ref = lookup(items.next(), refs)?;
+    let parent = match items.next() {
+        Some(Nil) => None,
+        id => Some(lookup(id, refs)?),
+    };
+
+    let mut env = Env::new(parent);
+    // an image can't grant itself more than the interpreter it's loaded into has
+    env.inherit_settings(&env_ref.borrow());
+    env.truthiness = match items.next() {
+        Some(Symbol(ref t)) if t == "scheme" => Truthiness::Scheme,
+        Some(Symbol(ref t)) if t == "legacy" => Truthiness::Legacy,
+        _ => return bad_image("env without a truthiness"),
+    };
+
+    for var in items {
+        match var {
+            List(mut var) if var.len() == 2 => {
+                let value = read_value(var.pop().unwrap(), refs)?;
+                let name = read_symbol(var.pop().unwrap())?;
+                env.define(&name, value)?;
+            }
+            _ => return bad_image("expected a (<name> <value>) pair"),
+        }
+    }
+
+    Ok((env_ref, env))
+}
+
+fn read_value(value: Value, refs: &Refs) -> Result<Value, Error> {
+    let mut items = match value {
+        List(items) => items.into_iter(),
+        atom => return Ok(atom),
+    };
+
+    let tag = match items.next() {
+        Some(Symbol(tag)) => tag,
+        _ => return bad_image("untagged list"),
+    };
+
+    match tag.as_str() {
+        "list" => Ok(List(items.map(|item| read_value(item, refs)).collect::<Result<_, _>>()?)),
+
+        "symbol" => match (items.next(), items.next()) {
+            (Some(Str(name)), None) => Ok(Symbol(name)),
+            _ => bad_image("invalid symbol"),
+        },
+
+        "proc" => {
+            let env = lookup(items.next(), refs)?;
+            let params = match items.next() {
+                Some(List(params)) => params.into_iter()
+                    .map(read_symbol)
+                    .collect::<Result<_, _>>()?,
+                _ => return bad_image("proc without params"),
+            };
+            let body = match (items.next(), items.next()) {
+                (Some(body), None) => read_value(body, refs)?,
+                _ => return bad_image("proc without a body"),
+            };
+
+            Ok(Proc(Box::new(LispProc { params, body, env })))
+        }
+
+        tag => bad_image(format!("unknown tag '{}'", tag)),
+    }
+}
+
+fn read_symbol(value: Value) -> Result<String, Error> {
+    match value {
+        Symbol(name) => Ok(name),
+        List(mut items) => match (items.len(), items.pop(), items.pop()) {
+            (2, Some(Str(name)), Some(Symbol(ref tag))) if tag == "symbol" => Ok(name),
+            _ => bad_image("expected a symbol"),
+        },
+        _ => bad_image("expected a symbol"),
+    }
+}
+
+fn lookup(id: Option<Value>, refs: &Refs) -> Result<EnvRef, Error> {
+    match id {
+        Some(Integer(id)) => match refs.ids.get(&id) {
+            Some(env) => Ok(env.clone()),
+            None => bad_image(format!("no env with id {}", id)),
+        },
+        Some(Symbol(ref tag)) if tag == "frozen" => match &refs.frozen {
+            Some(env) => Ok(env.clone()),
+            None => bad_image("saved on top of a frozen env, which there isn't one of here"),
+        },
+        _ => bad_image("expected an env id"),
+    }
+}
+
+fn bad_image<T, S: Into<String>>(msg: S) -> Result<T, Error> {
+    Err(RunError::BadImage(msg.into()).into())
+}
+// }}}
@@ -5,20 +5,42 @@ extern crate failure_derive;
 
 mod arithmetic;
 mod builtins;
+mod convert;
 mod env;
 mod eval;
 mod errors;
 mod file;
+mod frozen;
+mod image;
+mod limits;
 mod log;
+mod modules;
 mod parser;
+mod ports;
+mod printer;
+mod sandbox;
+#[cfg(feature = "serde")]
+mod serde_value;
+mod shared;
 pub mod values;
 
 use failure::Error;
-use std::cell::RefCell;
-use std::rc::Rc;
 
-use crate::values::Value;
+use crate::builtins::BUILTINS;
+use crate::values::{NativeFn, Truthiness, Value};
 use crate::env::*;
+use crate::shared::{Lock, Shared};
+
+pub use crate::convert::{FromValue, IntoResult, IntoValue, NativeFunction};
+pub use crate::errors::{ParseError, RunError};
+pub use crate::frozen::FrozenEnv;
+pub use crate::limits::{InterruptHandle, Limits, DEFAULT_MAX_DEPTH, STACK_PER_LEVEL};
+pub use crate::parser::is_incomplete;
+pub use crate::ports::Port;
+pub use crate::sandbox::{Capability, InterpreterBuilder};
+pub use crate::shared::MaybeSync;
+#[cfg(feature = "serde")]
+pub use crate::serde_value::{from_value, to_value, ConvertError};
 
 #[derive(Clone)]
 pub struct Interpreter {
@@ -26,28 +48,1013 @@ pub struct Interpreter {
 }
 
 impl Interpreter {
-    /// evaluate a string as lisp code
+    /// evaluate a string as lisp code, every form in it in turn, and give
+    /// back the value of the last one
     pub fn run<S: Into<String>>(&self, code: S) -> Result<Value, Error> {
-        // parse into an s-expression
-        let sexp = Value::new(code.into())?;
+        // parse into s-expressions
+        let sexps = Value::parse_all(&code.into())?;
+
+        // log::debug(format!("{:?}", sexps));
+        self.env.borrow().budget.start();
+        let mut result = Value::Nil;
+        for sexp in sexps {
+            result = eval::eval(sexp, self.env.clone())?;
+        }
+        Ok(result)
+    }
+
+    /// choose which values count as false in conditions. only #f is
+    /// false by default; see `Truthiness` for the alternative.
+    pub fn set_truthiness(&self, truthiness: Truthiness) {
+        self.env.borrow_mut().truthiness = truthiness;
+    }
+
+    /// limit how much work each call to `run` may do. exceeding a limit makes
+    /// it fail with `RunError::LimitExceeded`.
+    pub fn set_limits(&self, limits: Limits) {
+        self.env.borrow().budget.set_limits(limits);
+    }
 
-        // log::debug(format!("{:?}", sexp));
-        eval::eval(sexp, self.env.clone())
+    pub fn limits(&self) -> Limits {
+        self.env.borrow().budget.limits()
+    }
+
+    /// something that can stop this interpreter from another thread, e.g.
+    /// when a script has been running for too long
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.env.borrow().budget.interrupt_handle()
+    }
+
+    /// call a builtin, or a procedure defined in lisp, by name. the arguments
+    /// are used as they are, without being evaluated.
+    pub fn call(&self, name: &str, args: Vec<Value>) -> Result<Value, Error> {
+        self.env.borrow().budget.start();
+
+        if let Some(builtin) = BUILTINS.iter().find(|builtin| builtin.name == name) {
+            if !self.env.borrow().capabilities.allows(name, builtin.capability) {
+                return Err(RunError::NotGranted {
+                    name: name.to_owned(),
+                    capability: builtin.capability,
+                }.into());
+            }
+
+            // builtins evaluate their own arguments, so keep them from
+            // evaluating anything that isn't already a plain value
+            let args = args.into_iter()
+                .map(|arg| match arg {
+                    Value::Symbol(_) | Value::List(_) => {
+                        Value::List(vec![Value::Symbol("quote".to_owned()), arg])
+                    }
+                    arg => arg,
+                })
+                .collect();
+
+            return (builtin.run)(args, self.env.clone());
+        }
+
+        let procedure = match self.env.borrow().lookup(name) {
+            Some(value) if value.is_callable() => value,
+            Some(value) => return Err(RunError::UncallableValue {
+                name: name.to_owned(),
+                typename: value.get_type(),
+            }.into()),
+            None => return Err(RunError::Undefined(name.to_owned()).into()),
+        };
+
+        match procedure {
+            Value::Proc(p) => p.call_from(name.to_owned(), args, &self.env),
+            procedure => procedure.call_from(args, &self.env),
+        }
+    }
+
+    /// look up a global definition, converted to a rust type
+    pub fn get_global<T: FromValue>(&self, name: &str) -> Result<T, Error> {
+        match self.env.borrow().lookup(name) {
+            Some(value) => T::from_value(value, name),
+            None => Err(RunError::Undefined(name.to_owned()).into()),
+        }
+    }
+
+    /// define (or redefine) a global from a rust value, replacing an import
+    /// of the same name if there is one
+    pub fn set_global<T: IntoValue>(&self, name: &str, value: T) {
+        self.env.borrow_mut().redefine(name, value.into_value());
+    }
+
+    /// define a rust function that lisp code can call, like
+    /// `|x: f64, y: f64| x.hypot(y)`. its arguments and return value are
+    /// converted with `FromValue` and `IntoValue`, and calling it with the
+    /// wrong number of arguments is an error.
+    pub fn register_fn<F, Args>(&self, name: &str, func: F)
+    where
+        F: NativeFunction<Args> + MaybeSync + 'static,
+    {
+        let fn_name = name.to_owned();
+        let native = NativeFn::new(name, move |args| func.call_with(&fn_name, args));
+        self.env.borrow_mut().redefine(name, Value::Native(native));
+    }
+
+    /// make the program's arguments available to lisp code as `command-line`,
+    /// a list of strings starting with the script name
+    pub fn set_command_line(&self, args: Vec<String>) {
+        let args = args.into_iter().map(Value::Str).collect();
+        self.env.borrow_mut().redefine("command-line", Value::List(args));
+    }
+
+    /// forget everything that has been defined, keeping the settings
+    pub fn reset(&self) {
+        // an interpreter made from a FrozenEnv keeps starting from it
+        let parent = self.env.borrow().parent.clone().filter(|parent| parent.borrow().frozen);
+        let mut env = Env::new(parent);
+        env.inherit_settings(&self.env.borrow());
+        *self.env.borrow_mut() = env;
+    }
+
+    /// start setting up an interpreter with only some builtins available
+    pub fn builder() -> InterpreterBuilder {
+        InterpreterBuilder::new()
+    }
+
+    /// how to use a builtin, and what it does
+    pub fn builtin_doc(&self, name: &str) -> Option<&'static str> {
+        BUILTINS.iter()
+            .find(|builtin| builtin.name == name)
+            .map(|builtin| builtin.doc)
+    }
+
+    /// list every name that means something right now: the builtins, and
+    /// everything defined in the environment
+    pub fn defined_names(&self) -> Vec<String> {
+        let capabilities = self.env.borrow().capabilities.clone();
+        let mut names: Vec<String> = BUILTINS.iter()
+            .filter(|builtin| capabilities.allows(builtin.name, builtin.capability))
+            .map(|builtin| builtin.name.to_string())
+            .collect();
+
+        let mut env = Some(self.env.clone());
+        while let Some(current) = env {
+            names.extend(current.borrow().vars.keys().cloned());
+            env = current.borrow().parent.clone();
+        }
+
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+/// if an error came from calling `(exit)`, the status it asked for
+pub fn exit_status(err: &Error) -> Option<i32> {
+    match err.downcast_ref::<RunError>() {
+        Some(RunError::Exit(status)) => Some(*status),
+        _ => None,
     }
 }
 
 impl Default for Interpreter {
     fn default() -> Self {
-        Interpreter { env: Rc::new(RefCell::new(Env::new(None))) }
+        Interpreter { env: Shared::new(Lock::new(Env::new(None))) }
     }
 }
 
 // {{{ tests
 #[cfg(test)]
 mod tests {
+    use super::{exit_status, image, Capability, Interpreter, Limits, Port};
+    use crate::values::{Truthiness, Value};
+    use crate::values::Value::*;
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn string_escapes() {
+        let interp = Interpreter::default();
+        assert_eq!(interp.run(r#""a\nb\tc""#).unwrap(), Str("a\nb\tc".to_owned()));
+        assert_eq!(interp.run(r#""say \"hi\"""#).unwrap(), Str("say \"hi\"".to_owned()));
+        assert_eq!(interp.run(r#""back\\slash""#).unwrap(), Str("back\\slash".to_owned()));
+        assert_eq!(interp.run(r#""\x41;\u{3bb}""#).unwrap(), Str("Aλ".to_owned()));
+        assert!(interp.run(r#""\q""#).is_err());
+        assert!(interp.run(r#""\x41""#).is_err());
+    }
+
+    #[test]
+    fn string_serialize_round_trip() {
+        let interp = Interpreter::default();
+        let original = Str("tab\there \"quoted\" \\ \u{7}".to_owned());
+        let read_back = interp.run(original.serialize()).unwrap();
+        assert_eq!(read_back, original);
+    }
+
+    #[test]
+    fn integer_overflow_promotes_to_bignum() {
+        let interp = Interpreter::default();
+        let product = interp.run("(* 99999999999 99999999999)").unwrap();
+        assert_eq!(product.to_string(), "9999999999800000000001");
+        assert_eq!(interp.run("(+ 9223372036854775807 1)").unwrap().to_string(),
+                   "9223372036854775808");
+        assert_eq!(interp.run("(- 9223372036854775808 1)").unwrap(), Integer(i64::max_value()));
+        assert_eq!(interp.run("(/ 9999999999800000000001 99999999999)").unwrap(),
+                   Integer(99999999999));
+        assert_eq!(interp.run("(modulo 9999999999800000000002 99999999999)").unwrap(),
+                   Integer(1));
+        assert_eq!(interp.run("(< 9223372036854775807 9223372036854775808)").unwrap(),
+                   Bool(true));
+    }
+
+    #[test]
+    fn exact_rationals() {
+        let interp = Interpreter::default();
+        assert_eq!(interp.run("(/ 1 2)").unwrap().to_string(), "1/2");
+        assert_eq!(interp.run("(/ 4 2)").unwrap(), Integer(2));
+        assert_eq!(interp.run("(+ 1/3 2/3)").unwrap(), Integer(1));
+        assert_eq!(interp.run("(* 2/4 3)").unwrap().to_string(), "3/2");
+        assert_eq!(interp.run("(+ 1/2 0.25)").unwrap(), Float(0.75));
+        assert_eq!(interp.run("(< 1/3 0.34)").unwrap(), Bool(true));
+        assert_eq!(interp.run("(numerator 6/4)").unwrap(), Integer(3));
+        assert_eq!(interp.run("(denominator 6/4)").unwrap(), Integer(2));
+        assert_eq!(interp.run("(exact->inexact 1/4)").unwrap(), Float(0.25));
+        assert_eq!(interp.run("(inexact->exact 0.5)").unwrap().to_string(), "1/2");
+    }
+
+    #[test]
+    fn integer_division() {
+        let interp = Interpreter::default();
+        assert_eq!(interp.run("(modulo -7 2)").unwrap(), Integer(1));
+        assert_eq!(interp.run("(modulo 7 -2)").unwrap(), Integer(-1));
+        assert_eq!(interp.run("(remainder -7 2)").unwrap(), Integer(-1));
+        assert_eq!(interp.run("(quotient -7 2)").unwrap(), Integer(-3));
+        assert_eq!(interp.run("(floor/ -7 2)").unwrap().to_string(), "(-4 1)");
+        assert!(interp.run("(quotient 1 0)").is_err());
+    }
+
+    #[test]
+    fn math_library() {
+        let interp = Interpreter::default();
+        assert_eq!(interp.run("(sqrt 16)").unwrap(), Integer(4));
+        assert_eq!(interp.run("(sqrt 1/4)").unwrap().to_string(), "1/2");
+        assert_eq!(interp.run("(sqrt 2)").unwrap(), Float(2f64.sqrt()));
+        assert_eq!(interp.run("(expt 2 100)").unwrap().to_string(),
+                   "1267650600228229401496703205376");
+        assert_eq!(interp.run("(expt 2 -2)").unwrap().to_string(), "1/4");
+        assert_eq!(interp.run("(expt 4 0.5)").unwrap(), Float(2.0));
+        assert_eq!(interp.run("(expt -1 4000000001)").unwrap(), Integer(-1));
+        assert!(interp.run("(expt 10 4000000000)").is_err());
+        assert!(interp.run("(expt 1/7 400000000)").is_err());
+        assert_eq!(interp.run("(inexact->exact +inf.0)").unwrap_err().to_string(),
+                   "inf has no exact representation");
+        assert_eq!(interp.run("(atan 1 1)").unwrap(), Float(1f64.atan2(1.0)));
+        assert_eq!(interp.run("(round 2.5)").unwrap(), Float(2.0));
+        assert_eq!(interp.run("(round 7/2)").unwrap(), Integer(4));
+        assert_eq!(interp.run("(floor -7/2)").unwrap(), Integer(-4));
+        assert_eq!(interp.run("(abs -9223372036854775808)").unwrap().to_string(),
+                   "9223372036854775808");
+        assert_eq!(interp.run("(max 1 2.0 3)").unwrap(), Float(3.0));
+        assert_eq!(interp.run("(gcd 12 18)").unwrap(), Integer(6));
+        assert_eq!(interp.run("(lcm 4 6)").unwrap(), Integer(12));
+        assert_eq!(interp.run("(exact-integer-sqrt 17)").unwrap().to_string(), "(4 1)");
+    }
+
+    #[test]
+    fn number_predicates() {
+        let interp = Interpreter::default();
+        assert_eq!(interp.run("(number? \"1\")").unwrap(), Bool(false));
+        assert_eq!(interp.run("(integer? 2.0)").unwrap(), Bool(true));
+        assert_eq!(interp.run("(integer? 1/2)").unwrap(), Bool(false));
+        assert_eq!(interp.run("(zero? 0.0)").unwrap(), Bool(true));
+        assert_eq!(interp.run("(even? -4)").unwrap(), Bool(true));
+        assert_eq!(interp.run("(odd? -3)").unwrap(), Bool(true));
+        assert_eq!(interp.run("(nan? NAN)").unwrap(), Bool(true));
+        assert_eq!(interp.run("(infinite? INF)").unwrap(), Bool(true));
+        assert!(interp.run("(zero? \"0\")").is_err());
+    }
+
+    #[test]
+    fn radix_literals_and_bitwise() {
+        let interp = Interpreter::default();
+        assert_eq!(interp.run("#xff").unwrap(), Integer(255));
+        assert!(interp.run("#xzz").is_err());
+        assert!(interp.run("(quote #b102)").is_err());
+        assert_eq!(interp.run("#b-1010").unwrap(), Integer(-10));
+        assert_eq!(interp.run("#o17").unwrap(), Integer(15));
+        assert_eq!(interp.run("(number->string 255 16)").unwrap(), Str("ff".to_owned()));
+        assert_eq!(interp.run("(number->string -5 2)").unwrap(), Str("-101".to_owned()));
+        assert_eq!(interp.run("(bitwise-and #b1100 #b1010)").unwrap(), Integer(0b1000));
+        assert_eq!(interp.run("(bitwise-or #b1100 #b1010)").unwrap(), Integer(0b1110));
+        assert_eq!(interp.run("(bitwise-xor #b1100 #b1010)").unwrap(), Integer(0b0110));
+        assert_eq!(interp.run("(bitwise-and -1 #xf0)").unwrap(), Integer(0xf0));
+        assert_eq!(interp.run("(bitwise-not 0)").unwrap(), Integer(-1));
+        assert_eq!(interp.run("(arithmetic-shift 1 70)").unwrap().to_string(),
+                   "1180591620717411303424");
+        assert_eq!(interp.run("(arithmetic-shift -5 -1)").unwrap(), Integer(-3));
+        assert!(interp.run("(arithmetic-shift 1 (expt 10 12))").is_err());
+        assert_eq!(interp.run("(arithmetic-shift 1 (- 0 (expt 10 12)))").unwrap(), Integer(0));
+        assert_eq!(interp.run("(bit-count #b10110)").unwrap(), Integer(3));
+        assert_eq!(interp.run("(bit-count -1)").unwrap(), Integer(0));
+        assert!(interp.run("(bitwise-and 1.5 1)").is_err());
+    }
+
+    #[test]
+    fn chained_comparisons() {
+        let interp = Interpreter::default();
+        assert_eq!(interp.run("(< 1 2 3)").unwrap(), Bool(true));
+        assert_eq!(interp.run("(< 1 3 2)").unwrap(), Bool(false));
+        assert_eq!(interp.run("(>= 3 3 1/2 0.1)").unwrap(), Bool(true));
+        assert_eq!(interp.run("(= 1 1.0 2/2)").unwrap(), Bool(true));
+        assert_eq!(interp.run("(< 5)").unwrap(), Bool(true));
+        assert!(interp.run("(< 1 \"2\")").is_err());
+        assert_eq!(interp.run("(equal? \"a\" \"a\")").unwrap(), Bool(true));
+        assert_eq!(interp.run("(equal? '(1 2) '(1 2))").unwrap(), Bool(true));
+    }
+
+    #[test]
+    fn short_circuiting_and_or() {
+        let interp = Interpreter::default();
+        assert_eq!(interp.run("(and)").unwrap(), Bool(true));
+        assert_eq!(interp.run("(or)").unwrap(), Bool(false));
+        assert_eq!(interp.run("(and 1 2 3)").unwrap(), Integer(3));
+        assert_eq!(interp.run("(or #f 2 (car 1))").unwrap(), Integer(2));
+        assert_eq!(interp.run("(and #f (car 1))").unwrap(), Bool(false));
+    }
+
+    /// one of each `Value` variant, and whether it's true in scheme and legacy mode
+    fn truthiness_cases() -> Vec<(&'static str, bool, bool)> {
+        vec![
+            ("#t",                   true,  true),
+            ("#f",                   false, false),
+            ("nil",                  true,  false),
+            ("'()",                  true,  false),
+            ("'(1)",                 true,  true),
+            ("'(#f)",                true,  true),
+            ("0",                    true,  false),
+            ("1",                    true,  true),
+            ("0.0",                  true,  false),
+            ("0.5",                  true,  true),
+            ("1/2",                  true,  true),
+            ("99999999999999999999", true,  true),
+            ("\"\"",                 true,  true),
+            ("'sym",                 true,  true),
+            ("(lambda (x) x)",       true,  true),
+        ]
+    }
+
+    #[test]
+    fn scheme_truthiness() {
+        let interp = Interpreter::default();
+        for (expr, truthy, _) in truthiness_cases() {
+            let expected = if truthy { Integer(1) } else { Integer(2) };
+            assert_eq!(interp.run(format!("(if {} 1 2)", expr)).unwrap(), expected, "if {}", expr);
+            assert_eq!(interp.run(format!("(cond ({} 1) (else 2))", expr)).unwrap(), expected,
+                       "cond {}", expr);
+            assert_eq!(interp.run(format!("(not {})", expr)).unwrap(), Bool(!truthy), "not {}", expr);
+            assert_eq!(interp.run(format!("(and {} 'yes)", expr)).unwrap() == Symbol("yes".to_owned()),
+                       truthy, "and {}", expr);
+            assert_eq!(interp.run(format!("(or {} 'no)", expr)).unwrap() != Symbol("no".to_owned()),
+                       truthy, "or {}", expr);
+        }
+    }
+
+    #[test]
+    fn legacy_truthiness() {
+        let interp = Interpreter::default();
+        interp.set_truthiness(Truthiness::Legacy);
+        for (expr, _, truthy) in truthiness_cases() {
+            let expected = if truthy { Integer(1) } else { Integer(2) };
+            assert_eq!(interp.run(format!("(if {} 1 2)", expr)).unwrap(), expected, "if {}", expr);
+            assert_eq!(interp.run(format!("(cond ({} 1) (else 2))", expr)).unwrap(), expected,
+                       "cond {}", expr);
+            assert_eq!(interp.run(format!("(not {})", expr)).unwrap(), Bool(!truthy), "not {}", expr);
+            assert_eq!(interp.run(format!("(and {} 'yes)", expr)).unwrap() == Symbol("yes".to_owned()),
+                       truthy, "and {}", expr);
+        }
+
+        // inside procedures too
+        interp.run("(define (truthy? x) (if x #t #f))").unwrap();
+        assert_eq!(interp.run("(truthy? 0)").unwrap(), Bool(false));
+    }
+
+    #[test]
+    fn incomplete_forms() {
+        assert!(super::is_incomplete("(define (f x)"));
+        assert!(super::is_incomplete("(display \"a ( b"));
+        assert!(super::is_incomplete("(f ; a comment )\n"));
+        assert!(!super::is_incomplete("(f \"(\" ; (\n)"));
+        assert!(!super::is_incomplete("(f))"));
+        assert!(!super::is_incomplete("x"));
+    }
+
+    #[test]
+    fn multi_line_forms() {
+        let interp = Interpreter::default();
+        interp.run("(define (add-one x)\n  ; add one to x\n\t(+ x 1))").unwrap();
+        assert_eq!(interp.run("(add-one\n 41)").unwrap(), Integer(42));
+
+        // several forms on one line all run
+        assert_eq!(interp.run("(define x 2) (* x x)").unwrap(), Integer(4));
+        interp.run_source("(define a 1) (define b (+ a 1))\n(define c (+ b 1)) c", "test.scm", false).unwrap();
+        assert_eq!(interp.run("c").unwrap(), Integer(3));
+        assert!(Value::new("1 2".to_owned()).is_err());
+    }
+
+    #[test]
+    fn run_source_scripts() {
+        let interp = Interpreter::default();
+        let script = "#!/usr/bin/env lisprs\n(define (sq x)\n  (* x x))\n\n(define y (sq 3))\n(car 5)\n(define z 1)";
+        let err = interp.run_source(script, "test.scm", false).unwrap_err();
+        assert!(err.to_string().contains("test.scm:6"));
+        assert_eq!(interp.run("y").unwrap(), Integer(9));
+        assert_eq!(interp.run("z").unwrap(), Str("z".to_owned()));
+
+        interp.run_source(script, "test.scm", true).unwrap();
+        assert_eq!(interp.run("z").unwrap(), Integer(1));
+
+        interp.set_command_line(vec!["test.scm".to_owned(), "-v".to_owned()]);
+        assert_eq!(interp.run("command-line").unwrap().serialize(), "(\"test.scm\" \"-v\")");
+    }
+
+    #[test]
+    fn exit_stops_scripts() {
+        let interp = Interpreter::default();
+        let err = interp.run_source("(car 1)\n(exit 3)\n(define x 1)", "test.scm", true).unwrap_err();
+        assert_eq!(exit_status(&err), Some(3));
+        assert_eq!(interp.run("x").unwrap(), Str("x".to_owned()));
+
+        assert_eq!(exit_status(&interp.run("(exit)").unwrap_err()), Some(0));
+        assert_eq!(exit_status(&interp.run("(exit #f)").unwrap_err()), Some(1));
+        assert_eq!(exit_status(&interp.run("(car 1)").unwrap_err()), None);
+        assert!(exit_status(&interp.run("(exit \"no\")").unwrap_err()).is_none());
+    }
+
+    #[test]
+    fn image_round_trip() {
+        let interp = Interpreter::default();
+        interp.run("(define (make-counter start) (lambda () start))").unwrap();
+        interp.run("(define from-ten (make-counter 10))").unwrap();
+        interp.run("(define (fact n) (if (= n 0) 1 (* n (fact (- n 1)))))").unwrap();
+        interp.run("(define data '(1 2.0 \"two\\n\" 1/3 #f))").unwrap();
+        let image = image::dump(&interp.env);
+
+        let restored = Interpreter::default();
+        restored.run("(define gone 1)").unwrap();
+        image::restore(&image, &restored.env).unwrap();
+
+        assert_eq!(restored.run("(from-ten)").unwrap(), Integer(10));
+        assert_eq!(restored.run("(fact 5)").unwrap(), Integer(120));
+        assert_eq!(restored.run("data").unwrap(), interp.run("data").unwrap());
+        assert_eq!(restored.run("gone").unwrap(), Str("gone".to_owned()));
+        assert_eq!(image::dump(&restored.env), image);
+
+        assert!(image::restore("(lisprs-image 1 (env 0 nil scheme (f (proc 3 () 1))))", &restored.env).is_err());
+        assert_eq!(restored.run("(fact 3)").unwrap(), Integer(6));
+
+        // a frozen base isn't saved, and the image goes back on top of one
+        let base = Interpreter::default();
+        base.run("(define prelude-value 1)").unwrap();
+        base.run("(define (add-prelude n) (+ n prelude-value))").unwrap();
+        let base = base.freeze();
+        let worker = base.interpreter();
+        worker.run("(define add add-prelude)").unwrap();
+        worker.run("(define two (add 1))").unwrap();
+        let image = image::dump(&worker.env);
+        assert!(!image.contains("(prelude-value 1)"), "{}", image);
+
+        let restored = base.interpreter();
+        image::restore(&image, &restored.env).unwrap();
+        assert_eq!(restored.run("(add two)").unwrap(), Integer(3));
+        assert_eq!(restored.run("prelude-value").unwrap(), Integer(1));
+        assert_eq!(image::dump(&restored.env), image);
+        assert!(image::restore(&image, &Interpreter::default().env).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_conversions() {
+        use serde_derive::{Deserialize, Serialize};
+        use super::{from_value, to_value};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        enum Shape { Dot, Circle(f64), Rect { w: i64, h: i64 } }
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct Drawing { name: String, shapes: Vec<Shape>, scale: Option<u8> }
+
+        let drawing = Drawing {
+            name: "doodle".to_owned(),
+            shapes: vec![Shape::Dot, Shape::Circle(1.5), Shape::Rect { w: 2, h: 3 }],
+            scale: None,
+        };
+        let value = to_value(&drawing).unwrap();
+        assert_eq!(value.serialize(),
+                   "((name \"doodle\") (shapes (Dot (Circle 1.5) (Rect ((w 2) (h 3))))) (scale nil))");
+        assert_eq!(from_value::<Drawing>(value).unwrap(), drawing);
+
+        let interp = Interpreter::default();
+        let value = interp.run("'(1 2.5 \"s\" sym #t 1/3 99999999999999999999 (nested))").unwrap();
+        let json = serde_json::to_string(&value).unwrap();
+        let back: crate::values::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.serialize(), value.serialize());
+
+        // deserializing a value as a value gives back the same one
+        let back: crate::values::Value = from_value(value.clone()).unwrap();
+        assert_eq!(back.serialize(), value.serialize());
+        // and rust types that want strings or floats still take symbols and rationals
+        let value = interp.run("'(sym 1/4)").unwrap();
+        assert_eq!(from_value::<(String, f64)>(value).unwrap(), ("sym".to_owned(), 0.25));
+
+        assert!(serde_json::to_string(&interp.run("(lambda (x) x)").unwrap()).is_err());
+    }
+
+    #[test]
+    fn typed_conversions() {
+        use super::{FromValue, IntoValue};
+        use std::collections::HashMap;
+
+        let interp = Interpreter::default();
+        let list = interp.run("'((\"a\" 1) (\"b\" 2))").unwrap();
+        let pairs = Vec::<(String, i64)>::from_value(list.clone(), "test").unwrap();
+        assert_eq!(pairs, vec![("a".to_owned(), 1), ("b".to_owned(), 2)]);
+
+        let map = HashMap::<String, i64>::from_value(list, "test").unwrap();
+        assert_eq!(map["b"], 2);
+        assert_eq!(map.into_value().get_type(), "List");
+
+        assert_eq!(Option::<bool>::from_value(Nil, "test").unwrap(), None);
+        assert_eq!(f64::from_value(interp.run("1/2").unwrap(), "test").unwrap(), 0.5);
+        assert_eq!((1, "two", vec![3.5]).into_value().serialize(), "(1 \"two\" (3.5))");
+
+        let err = i64::from_value(Str("1".to_owned()), "test").unwrap_err();
+        assert_eq!(err.to_string(), "test: expected a Integer, got a Str instead");
+    }
+
+    #[test]
+    fn native_functions() {
+        let interp = Interpreter::default();
+        interp.register_fn("hypot", |x: f64, y: f64| x.hypot(y));
+        interp.register_fn("repeat", |s: String, n: i64| s.repeat(n as usize));
+        interp.register_fn("checked-div", |a: i64, b: i64| -> Result<i64, failure::Error> {
+            a.checked_div(b).ok_or_else(|| failure::err_msg("division by zero"))
+        });
+
+        assert_eq!(interp.run("(hypot 3 4)").unwrap(), Float(5.0));
+        assert_eq!(interp.run("(repeat \"ab\" (+ 1 2))").unwrap(), Str("ababab".to_owned()));
+        assert_eq!(interp.run("(checked-div 7 2)").unwrap(), Integer(3));
+        assert!(interp.run("(checked-div 7 0)").is_err());
+        assert!(interp.run("(hypot 3)").unwrap_err().to_string().contains("expected 2 params"));
+        assert!(interp.run("(hypot \"3\" 4)").is_err());
+
+        interp.run("(define (twice f x) (f (f x)))").unwrap();
+        interp.register_fn("inc", |n: i64| n + 1);
+        assert_eq!(interp.run("(twice inc 1)").unwrap(), Integer(3));
+    }
+
+    #[test]
+    fn calling_from_rust() {
+        use super::IntoValue;
+
+        let interp = Interpreter::default();
+        interp.run("(define (greet name) (cat \"hi \" name))").unwrap();
+        assert_eq!(interp.call("greet", vec!["bob".into_value()]).unwrap(), Str("hi bob".to_owned()));
+
+        let list = interp.run("'(a (b c))").unwrap();
+        assert_eq!(interp.call("car", vec![list.clone()]).unwrap(), Symbol("a".to_owned()));
+        assert_eq!(interp.call("length", vec![list]).unwrap(), Integer(2));
+        assert_eq!(interp.call("+", vec![Integer(1), Integer(2), Integer(3)]).unwrap(), Integer(6));
+
+        assert!(interp.call("nope", vec![]).unwrap_err().to_string().contains("not defined"));
+        interp.set_global("x", 5);
+        assert!(interp.call("x", vec![]).is_err());
+
+        assert_eq!(interp.get_global::<i64>("x").unwrap(), 5);
+        interp.set_global("names", vec!["a", "b"]);
+        assert_eq!(interp.get_global::<Vec<String>>("names").unwrap(), vec!["a", "b"]);
+        assert!(interp.get_global::<String>("x").is_err());
+        assert!(interp.get_global::<i64>("missing").is_err());
+
+        let adder = interp.run("(lambda (a b) (+ a b))").unwrap();
+        assert!(adder.is_callable());
+        assert_eq!(adder.call(&interp, vec![Integer(2), Integer(3)]).unwrap(), Integer(5));
+        assert!(Integer(1).call(&interp, vec![]).is_err());
+    }
+
+    #[test]
+    fn reset_keeps_settings() {
+        let interp = Interpreter::default();
+        interp.set_truthiness(Truthiness::Legacy);
+        interp.run("(define x 1)").unwrap();
+        assert!(interp.defined_names().contains(&"x".to_owned()));
+
+        interp.reset();
+        assert!(!interp.defined_names().contains(&"x".to_owned()));
+        assert_eq!(interp.run("(if 0 1 2)").unwrap(), Integer(2));
+    }
+
+    #[test]
+    fn sandboxed_builtins() {
+        let interp = Interpreter::builder().build();
+        assert_eq!(interp.run("(car (quote (1 2)))").unwrap(), Integer(1));
+        let err = interp.run("(+ 1 2)").unwrap_err().to_string();
+        assert!(err.contains("capability not granted"), "{}", err);
+        assert!(interp.call("+", vec![Integer(1)]).is_err());
+        assert!(!interp.defined_names().contains(&"+".to_owned()));
+
+        // closures run with the capabilities of the interpreter
+        interp.run("(define f (lambda (x) (exit x)))").unwrap();
+        assert!(interp.run("(f 1)").unwrap_err().to_string().contains("capability not granted"));
+
+        let interp = Interpreter::builder()
+            .allow(Capability::Math)
+            .deny("eval")
+            .build();
+        assert_eq!(interp.run("(+ 1 2)").unwrap(), Integer(3));
+        assert!(interp.run("(eval (quote 1))").is_err());
+
+        interp.reset();
+        assert!(interp.run("(eval (quote 1))").is_err());
+        assert!(interp.run("(cat \"a\" \"b\")").is_err());
+    }
+
+    #[test]
+    fn builtin_docs() {
+        let interp = Interpreter::default();
+        assert_eq!(interp.builtin_doc("car").unwrap(),
+                   "(car <list>)\n  return the first element of a populated list, or nil");
+        assert!(interp.builtin_doc("nope").is_none());
+        for builtin in super::BUILTINS {
+            assert!(builtin.doc.starts_with(&format!("({}", builtin.name)), "{}", builtin.name);
+        }
+    }
+
+    #[test]
+    fn resource_limits() {
+        // with a depth limit, endless recursion stops cleanly instead of
+        // overflowing the stack
+        let interp = Interpreter::default();
+        interp.set_limits(Limits { max_depth: Some(100), ..Limits::default() });
+        interp.run("(define f (lambda (n) (f (+ n 1))))").unwrap();
+        let err = interp.run("(f 0)").unwrap_err().to_string();
+        assert!(err.contains("depth limit of 100"), "{}", err);
+
+        // and so it does by default, on the stack a main thread gets
+        let deep = std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(|| {
+                let interp = Interpreter::default();
+                assert_eq!(interp.limits().max_depth, Some(crate::DEFAULT_MAX_DEPTH));
+                interp.run("(define f (lambda (n) (f (+ n 1))))").unwrap();
+                let err = interp.run("(f 0)").unwrap_err().to_string();
+                interp.run("(define fact (lambda (n) (if (= n 0) 1 (* n (fact (- n 1))))))").unwrap();
+                (err, interp.run("(fact 100)").unwrap().to_string())
+            })
+            .unwrap();
+        let (err, fact) = deep.join().unwrap();
+        assert!(err.contains("depth limit"), "{}", err);
+        assert!(fact.starts_with("93326215443944152681"));
+
+        let interp = Interpreter::builder()
+            .allow_all()
+            .limits(Limits { max_steps: Some(100), ..Limits::default() })
+            .build();
+        interp.run("(define count (lambda (n) (if (= n 0) 0 (count (- n 1)))))").unwrap();
+        assert_eq!(interp.run("(count 5)").unwrap(), Integer(0));
+        let err = interp.run("(count 50)").unwrap_err().to_string();
+        assert!(err.contains("step limit of 100"), "{}", err);
+        // every run gets a fresh budget
+        assert_eq!(interp.run("(count 5)").unwrap(), Integer(0));
+
+        // and so does every call from rust
+        interp.set_limits(Limits { max_steps: Some(50), ..Limits::default() });
+        let id = interp.run("(define id (lambda (x) x))").and_then(|_| interp.run("id")).unwrap();
+        for n in 0..100 {
+            assert_eq!(interp.call("id", vec![Integer(n)]).unwrap(), Integer(n));
+            assert_eq!(id.call(&interp, vec![Integer(n)]).unwrap(), Integer(n));
+        }
+
+        interp.set_limits(Limits { max_size: Some(3), ..Limits::default() });
+        assert!(interp.run("(append (quote (1 2)) (quote (3 4)))").is_err());
+        assert!(interp.run("(cat \"ab\" \"cd\")").is_err());
+        assert_eq!(interp.run("(cat \"a\" \"b\")").unwrap(), Str("ab".to_owned()));
+        // strings are measured in chars, like length does
+        assert_eq!(interp.run("(cat \"é\" \"ü\")").unwrap(), Str("éü".to_owned()));
+
+        // interrupting while it runs: the flag is set partway through, so
+        // whatever is evaluated after that stops
+        interp.set_limits(Limits::default());
+        interp.run("(define fib (lambda (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2))))))").unwrap();
+        let handle = interp.interrupt_handle();
+        interp.register_fn("interrupt", move || handle.interrupt());
+        let err = interp.run("(if (interrupt) (fib 40) 0)").unwrap_err().to_string();
+        assert_eq!(err, "interrupted");
+        assert_eq!(interp.run("(fib 5)").unwrap(), Integer(5));
+
+        // and an interrupt sent before a run starts stops it straight away,
+        // rather than being forgotten
+        interp.interrupt_handle().interrupt();
+        assert_eq!(interp.run("(fib 5)").unwrap_err().to_string(), "interrupted");
+        assert_eq!(interp.run("(fib 5)").unwrap(), Integer(5));
+    }
+
+    #[test]
+    fn frozen_env() {
+        let base = Interpreter::default();
+        base.run("(define x 1)").unwrap();
+        base.run("(define add-x (lambda (n) (+ n x)))").unwrap();
+        base.register_fn("double", |n: i64| n * 2);
+        let base = base.freeze();
+
+        let a = base.interpreter();
+        let b = base.interpreter();
+        a.run("(define y 10)").unwrap();
+        a.run("(define x 100)").unwrap();
+        assert_eq!(a.run("(add-x y)").unwrap(), Integer(11));
+        assert_eq!(a.run("x").unwrap(), Integer(100));
+        assert_eq!(b.run("x").unwrap(), Integer(1));
+        assert_eq!(b.run("y").unwrap(), Str("y".to_owned()));
+        assert_eq!(b.call("double", vec![Integer(4)]).unwrap(), Integer(8));
+        assert_eq!(b.get_global::<i64>("x").unwrap(), 1);
+
+        // and they don't see each other's libraries or load paths either
+        a.run("(define-library (a-only) (export z) (begin (define z 3)))").unwrap();
+        a.add_load_path("a-only");
+        assert!(b.run("(import (a-only))").is_err());
+        assert!(!b.load_path().contains(&std::path::PathBuf::from("a-only")));
+        a.run("(import (a-only))").unwrap();
+        assert_eq!(a.run("z").unwrap(), Integer(3));
+
+        a.reset();
+        assert_eq!(a.run("x").unwrap(), Integer(1));
+        assert!(!a.defined_names().contains(&"y".to_owned()));
+
+        // procedures from the base run under the interpreter's own limits
+        let c = base.interpreter();
+        c.set_limits(Limits { max_steps: Some(3), ..Limits::default() });
+        assert!(c.run("(add-x 1)").is_err());
+        assert_eq!(b.run("(add-x 1)").unwrap(), Integer(2));
+
+        // and so they do when they're called from rust
+        let add_x = b.run("add-x").unwrap();
+        c.set_limits(Limits { max_steps: Some(1), ..Limits::default() });
+        assert!(add_x.call(&c, vec![Integer(1)]).is_err());
+        assert_eq!(add_x.call(&b, vec![Integer(1)]).unwrap(), Integer(2));
+        c.set_limits(Limits::default());
+        b.set_truthiness(Truthiness::Legacy);
+        let test = base.interpreter().run("(lambda (x) (if x 1 2))").unwrap();
+        assert_eq!(test.call(&b, vec![Integer(0)]).unwrap(), Integer(2));
+        assert_eq!(test.call(&c, vec![Integer(0)]).unwrap(), Integer(1));
+    }
+
+    #[test]
+    fn libraries() {
+        let interp = Interpreter::default();
+        interp.run("(define-library (util math)
+                      (export square (rename cube-of cube))
+                      (begin
+                        (define square (lambda (n) (* n n)))
+                        (define cube-of (lambda (n) (* n (square n))))
+                        (define secret 42)))").unwrap();
+
+        interp.run("(import (util math))").unwrap();
+        assert_eq!(interp.run("(+ (square 2) (cube 2))").unwrap(), Integer(12));
+        assert_eq!(interp.run("secret").unwrap(), Str("secret".to_owned()));
+
+        interp.run("(import (prefix (only (util math) square) m:))").unwrap();
+        assert_eq!(interp.run("(m:square 3)").unwrap(), Integer(9));
+        interp.run("(import (rename (except (util math) square) (cube c)))").unwrap();
+        assert_eq!(interp.run("(c 3)").unwrap(), Integer(27));
+        assert!(interp.run("(import (only (util math) secret))").is_err());
+
+        // another library exporting the same name is a collision
+        interp.run("(define-library (other) (export square) (begin (define square 0)))").unwrap();
+        let err = interp.run("(import (other))").unwrap_err().to_string();
+        assert!(err.contains("square is imported from both (util math) and (other)"), "{}", err);
+
+        // and so is defining over an import, until it's been undefined
+        let err = interp.run("(define cube 1)").unwrap_err().to_string();
+        assert!(err.contains("cube is imported from (util math)"), "{}", err);
+        assert_eq!(interp.run("(cube 2)").unwrap(), Integer(8));
+        interp.run("(undef cube)").unwrap();
+        interp.run("(define cube 1)").unwrap();
+
+        // and importing over a definition
+        interp.run("(define-library (more) (export more-things cube) (begin (define more-things 0) (define cube 0)))").unwrap();
+        let err = interp.run("(import (more))").unwrap_err().to_string();
+        assert!(err.contains("cube is already defined"), "{}", err);
+        // nothing from a failed import comes in
+        assert_eq!(interp.run("more-things").unwrap(), Str("more-things".to_owned()));
+        assert_eq!(interp.run("cube").unwrap(), Integer(1));
+        interp.run("(import (except (more) cube))").unwrap();
+        assert_eq!(interp.run("more-things").unwrap(), Integer(0));
+
+        // a library can only be defined once
+        let err = interp.run("(define-library (other) (export square) (begin (define square 1)))").unwrap_err();
+        assert!(err.to_string().contains("(other) is already defined"), "{}", err);
+
+        // libraries in files are found through the load path, and only loaded once
+        let dir = std::env::temp_dir().join(format!("lisprs-libraries-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("shapes")).unwrap();
+        let file = dir.join("shapes").join("circle.sld");
+        std::fs::write(&file, "(define-library (shapes circle)
+                                 (export area)
+                                 (import (util math))
+                                 (begin (define area (lambda (r) (* 3 (square r))))))").unwrap();
+
+        assert!(interp.run("(import (shapes circle))").unwrap_err().to_string().contains("no library"));
+        interp.add_load_path(&dir);
+        interp.run("(import (shapes circle))").unwrap();
+        assert_eq!(interp.run("(area 2)").unwrap(), Integer(12));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        interp.reset();
+        interp.run("(import (shapes circle))").unwrap();
+        assert_eq!(interp.run("(area 1)").unwrap(), Integer(3));
+    }
+
+    #[test]
+    fn load_and_include() {
+        let dir = std::env::temp_dir().join(format!("lisprs-load-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        let write = |name: &str, source: &str| std::fs::write(dir.join(name), source).unwrap();
+
+        write("main.scm", "(load \"lib/helpers.scm\")\n(define y (helper 1))");
+        write("lib/helpers.scm", "(define helper (lambda (n) (+ n 1)))\n(include \"consts.scm\")");
+        write("lib/consts.scm", "(define answer 42)");
+        write("lib/local.scm", "(define z 3)");
+        write("broken.scm", "(define a 1)\n(car 1)");
+        write("a.scm", "(load \"b.scm\")");
+        write("b.scm", "(load \"a.scm\")");
+
+        let interp = Interpreter::default();
+        interp.run_file(dir.join("main.scm")).unwrap();
+        assert_eq!(interp.run("y").unwrap(), Integer(2));
+        assert_eq!(interp.run("answer").unwrap(), Integer(42));
+
+        // include defines things where it's used; load always defines them globally
+        let path = dir.join("lib").join("local.scm");
+        interp.run(format!("(define f (lambda () (let ((z 0)) (include {:?}))))", path)).unwrap();
+        interp.run("(f)").unwrap();
+        assert_eq!(interp.run("z").unwrap(), Str("z".to_owned()));
+
+        let err = interp.run(format!("(load {:?})", dir.join("broken.scm"))).unwrap_err().to_string();
+        assert!(err.contains("broken.scm:2"), "{}", err);
+        let err = interp.run(format!("(load {:?})", dir.join("a.scm"))).unwrap_err().to_string();
+        assert!(err.contains("a.scm is already being loaded"), "{}", err);
+        let err = interp.run("(load \"nowhere.scm\")").unwrap_err().to_string();
+        assert!(err.contains("couldn't read nowhere.scm"), "{}", err);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ports() {
+        let interp = Interpreter::default();
+        let out = Port::buffer();
+        interp.set_output_port(out.clone());
+        interp.run("(display \"hi\")").unwrap();
+        interp.run("(newline)").unwrap();
+        interp.run("(write \"hi\")").unwrap();
+        assert_eq!(out.contents().unwrap(), "hi\n\"hi\"");
+
+        let captured = interp.run("(with-output-to-string (lambda () (display (list-ref (quote (1 2)) 2))))");
+        assert_eq!(captured.unwrap(), Str("2".to_owned()));
+        assert_eq!(out.contents().unwrap(), "hi\n\"hi\"");
+
+        interp.set_input_port(Port::from_string("first line\n(a \"b (\" 3) ; note\nλx"));
+        assert_eq!(interp.run("(read-line)").unwrap(), Str("first line".to_owned()));
+        assert_eq!(interp.run("(read)").unwrap(), List(vec![
+            Symbol("a".to_owned()), Str("b (".to_owned()), Integer(3),
+        ]));
+        assert_eq!(interp.run("(read)").unwrap(), Symbol("λx".to_owned()));
+        assert_eq!(interp.run("(eof-object? (read-char))").unwrap(), Bool(true));
+
+        interp.set_input_port(Port::from_string("'(1 2)\n' (a \"b\") '"));
+        let quoted = |items| List(vec![Symbol("quote".to_owned()), List(items)]);
+        assert_eq!(interp.run("(read)").unwrap(), quoted(vec![Integer(1), Integer(2)]));
+        assert_eq!(interp.run("(read)").unwrap(), quoted(vec![Symbol("a".to_owned()), Str("b".to_owned())]));
+        let err = interp.run("(read)").unwrap_err();
+        assert!(err.downcast_ref::<crate::errors::ParseError>().is_some());
+
+        let path = std::env::temp_dir().join(format!("lisprs-ports-{}.txt", std::process::id()));
+        interp.set_global("path", path.to_string_lossy().into_owned());
+        interp.run("(call-with-output-file path (lambda (port) (write (quote (x \"y\")) port)))").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "(x \"y\")");
+
+        interp.run("(define port (open-input-file path))").unwrap();
+        assert_eq!(interp.run("(read-char port)").unwrap(), Str("(".to_owned()));
+        assert_eq!(interp.run("(read port)").unwrap(), Symbol("x".to_owned()));
+        assert_eq!(interp.run("(read port)").unwrap(), Str("y".to_owned()));
+        assert!(interp.run("(read port)").is_err());
+        interp.run("(close-port port)").unwrap();
+        assert!(interp.run("(read-line port)").unwrap_err().to_string().contains("port is closed"));
+        assert!(interp.run("(display 1 port)").is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn printing() {
+        let interp = Interpreter::default();
+        let value = interp.run("'(\"a \\\"b\\\"\" c 2.0 (\"d\\n\"))").unwrap();
+        assert_eq!(value.to_string(), "(a \"b\" c 2.0 (d\n))");
+        assert_eq!(value.serialize(), "(\"a \\\"b\\\"\" c 2.0 (\"d\\n\"))");
+        assert_eq!(interp.run(format!("'{}", value.serialize())).unwrap(), value);
+
+        let proc = interp.run("(lambda (s) (string-append s \"!\"))").unwrap();
+        assert_eq!(proc.to_string(), "(lambda (s) (string-append s !))");
+        assert_eq!(proc.serialize(), "(lambda (s) (string-append s \"!\"))");
+
+        let out = Port::buffer();
+        interp.set_output_port(out.clone());
+        interp.run("(display '(\"x\" 1.5))").unwrap();
+        interp.run("(write '(\"x\" 1.5))").unwrap();
+        assert_eq!(out.contents().unwrap(), "(x 1.5)(\"x\" 1.5)");
+
+        let mut deep = List(vec![]);
+        for _ in 0..2000 {
+            deep = List(vec![deep]);
+        }
+        assert!(deep.serialize().starts_with("(((("));
+        assert!(deep.serialize().contains("(...)"));
+    }
+
+    #[test]
+    fn pretty_printing() {
+        let interp = Interpreter::default();
+        let value = interp.run("'(define (sign n) (cond ((< n 0) \"negative\") ((= n 0) \"zero\") (else \"positive\")))").unwrap();
+        assert_eq!(value.pretty(80), value.serialize());
+        assert_eq!(value.pretty(40), "\
+(define (sign n)
+  (cond ((< n 0) \"negative\")
+        ((= n 0) \"zero\")
+        (else \"positive\")))");
+
+        let proc = interp.run("(lambda (n) (let loop ((i n) (acc 1)) (if (= i 0) acc (loop (- i 1) (* acc i)))))").unwrap();
+        assert_eq!(proc.pretty(30), "\
+(lambda (n)
+  (let loop ((i n) (acc 1))
+    (if (= i 0)
+        acc
+        (loop (- i 1)
+              (* acc i)))))");
+
+        let numbers = interp.run("'(1 2 3 4 5 6 7 8 9 10 11 12)").unwrap();
+        assert_eq!(numbers.pretty(16), "(1 2 3 4 5 6 7 8\n 9 10 11 12)");
+
+        let out = Port::buffer();
+        interp.set_output_port(out.clone());
+        interp.run("(pp '(a \"b\"))").unwrap();
+        assert_eq!(out.contents().unwrap(), "(a \"b\")\n");
+
+        // saved definitions are pretty printed, and still run
+        interp.run("(define fact (lambda (n) (if (= n 0) 1 (* n (fact (- n 1))))))").unwrap();
+        interp.run("(define average (lambda (xs) (if (null? xs) 0 (/ (sum-of-the-list xs) (length-of-the-list xs)))))").unwrap();
+        let path = std::env::temp_dir().join(format!("lisprs-pretty-{}.scm", std::process::id()));
+        interp.save_env(&path).unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.lines().all(|line| line.chars().count() <= 80));
+        assert!(saved.contains("(define average\n  (lambda (xs)\n    (if (null? xs) 0 "));
+
+        let other = Interpreter::default();
+        other.run_file(&path).unwrap();
+        assert_eq!(other.run("(fact 5)").unwrap(), Integer(120));
+        assert_eq!(other.run("average").unwrap().serialize(), interp.run("average").unwrap().serialize());
+
+        // values too deep to print in full are left out, not cut short
+        let mut deep = List(vec![]);
+        for _ in 0..crate::printer::MAX_DEPTH {
+            deep = List(vec![deep]);
+        }
+        interp.set_global("deep", deep);
+        interp.save_env(&path).unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("(define average") && !saved.contains("deep") && !saved.contains("..."));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "sync")]
+    #[test]
+    fn sync_interpreters() {
+        fn is_send_sync<T: Send + Sync>() {}
+        is_send_sync::<Interpreter>();
+        is_send_sync::<super::FrozenEnv>();
+
+        let base = Interpreter::default();
+        base.run("(define square (lambda (n) (* n n)))").unwrap();
+        base.register_fn("offset", || 1);
+        let base = base.freeze();
+
+        let workers: Vec<_> = (0..4)
+            .map(|i| {
+                let base = base.clone();
+                std::thread::spawn(move || {
+                    let interp = base.interpreter();
+                    interp.set_global("i", i as i64);
+                    interp.run("(+ (square i) (offset))").unwrap()
+                })
+            })
+            .collect();
+
+        let results: Vec<super::Value> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+        assert_eq!(results, vec![Integer(1), Integer(2), Integer(5), Integer(10)]);
+    }
 }
 // }}}
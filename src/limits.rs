@@ -0,0 +1,149 @@
+use failure::Error;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::errors::RunError;
+use crate::shared::Lock;
+use crate::values::Value;
+
+/// how much work an interpreter may do before it gives up. `None` means
+/// there's no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// how many expressions one call to `run` may evaluate
+    pub max_steps: Option<u64>,
+    /// how deeply expressions may be nested while they're evaluated, which is
+    /// mostly how deeply procedures may recurse. each level takes native
+    /// stack, see `STACK_PER_LEVEL`, and running out of that aborts the whole
+    /// process. it's `DEFAULT_MAX_DEPTH` unless it's set.
+    pub max_depth: Option<usize>,
+    /// the most items a list, or chars a string, that an expression produces
+    /// may have. nothing else is measured, numbers included.
+    pub max_size: Option<usize>,
+}
+
+impl Default for Limits {
+    /// no limits, except on depth: recursing without end would otherwise
+    /// overflow the stack and take the whole process down with it
+    fn default() -> Self {
+        Limits {
+            max_steps: None,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_size: None,
+        }
+    }
+}
+
+/// about how much native stack one level of `max_depth` takes, in bytes, in
+/// a debug build. a release build takes about a quarter of it. threads get
+/// 2MB of stack by default and the main thread usually 8MB, so recursing
+/// much past a few hundred levels needs a thread with a bigger stack, like
+/// the one the lisprs binary runs on.
+pub const STACK_PER_LEVEL: usize = 8 * 1024;
+
+/// deep enough for most programs, and shallow enough to fit in half of the
+/// 8MB of stack a main thread usually gets, even in a debug build. threads
+/// with a smaller stack need a lower limit, and ones with a bigger stack can
+/// raise it.
+pub const DEFAULT_MAX_DEPTH: usize = 4 * 1024 * 1024 / STACK_PER_LEVEL;
+
+/// the limits an interpreter runs under, and how much of them has been used
+/// up so far. shared by every Env of an interpreter.
+#[derive(Debug, Default)]
+pub struct Budget {
+    limits: Lock<Limits>,
+    steps: AtomicU64,
+    depth: AtomicUsize,
+    interrupted: Arc<AtomicBool>,
+}
+
+/// lets another thread stop an interpreter, see `Interpreter::interrupt_handle`
+#[derive(Debug, Clone)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    /// stop whatever the interpreter is evaluating, which then fails with
+    /// `RunError::Interrupted`
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Budget {
+    pub fn limits(&self) -> Limits {
+        *self.limits.borrow()
+    }
+
+    pub fn set_limits(&self, limits: Limits) {
+        *self.limits.borrow_mut() = limits;
+    }
+
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle(self.interrupted.clone())
+    }
+
+    /// start evaluating something from the outside, with all of its steps.
+    /// an interrupt that's already been sent is kept, and stops it.
+    pub fn start(&self) {
+        if self.depth.load(Ordering::SeqCst) == 0 {
+            self.steps.store(0, Ordering::SeqCst);
+        }
+    }
+
+    /// account for evaluating one more expression, one level deeper than the
+    /// last. the level is left again when the guard is dropped.
+    pub fn enter(&self) -> Result<DepthGuard<'_>, Error> {
+        if self.interrupted.swap(false, Ordering::SeqCst) {
+            return Err(RunError::Interrupted.into());
+        }
+
+        let limits = self.limits();
+
+        let steps = self.steps.load(Ordering::SeqCst) + 1;
+        if let Some(max) = limits.max_steps {
+            if steps > max {
+                return exceeded("step", max);
+            }
+        }
+        self.steps.store(steps, Ordering::SeqCst);
+
+        let depth = self.depth.load(Ordering::SeqCst) + 1;
+        if let Some(max) = limits.max_depth {
+            if depth > max {
+                return exceeded("depth", max as u64);
+            }
+        }
+        self.depth.store(depth, Ordering::SeqCst);
+
+        Ok(DepthGuard(self))
+    }
+
+    /// make sure a value isn't bigger than allowed
+    pub fn check_size(&self, value: &Value) -> Result<(), Error> {
+        let size = match value {
+            Value::List(list) => list.len(),
+            Value::Str(s)     => s.chars().count(),
+            _ => return Ok(()),
+        };
+
+        match self.limits().max_size {
+            Some(max) if size > max => exceeded("size", max as u64),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct DepthGuard<'a>(&'a Budget);
+
+impl Drop for DepthGuard<'_> {
+    fn drop(&mut self) {
+        self.0.depth.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn exceeded<T>(limit: &str, max: u64) -> Result<T, Error> {
+    Err(RunError::LimitExceeded {
+        limit: limit.to_owned(),
+        max,
+    }.into())
+}
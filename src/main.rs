@@ -1,23 +1,66 @@
+mod commands;
+mod config;
+mod helper;
 mod log;
 
-use itertools::join;
-use std::path::PathBuf;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
 
-use lisprs::Interpreter;
+use failure::{format_err, Error};
+
+use lisprs::{Interpreter, Limits};
+use lisprs::values::Value;
+use crate::commands::{Action, Repl, COMMANDS};
+use crate::config::Config;
+use crate::helper::LispHelper;
 use rustyline::Editor;
 use rustyline::error::ReadlineError;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
+#[structopt(raw(setting = "structopt::clap::AppSettings::TrailingVarArg"))]
 struct Opt {
     #[structopt(short = "d", long = "debug")]
     debug: bool,
 
-    #[structopt(name = "INITFILE", parse(from_os_str), help = "scheme file to run on startup")]
+    #[structopt(long = "init", parse(from_os_str), help = "scheme file to run on startup")]
     initfile: Option<PathBuf>,
+
+    #[structopt(long = "history", parse(from_os_str),
+                help = "file to keep REPL history in [default: $XDG_DATA_HOME/lisprs/history]")]
+    history: Option<PathBuf>,
+
+    #[structopt(long = "load-image", parse(from_os_str), help = "image to start from, made with --save-image")]
+    load_image: Option<PathBuf>,
+
+    #[structopt(long = "save-image", parse(from_os_str), help = "save an image of everything defined before exiting")]
+    save_image: Option<PathBuf>,
+
+    #[structopt(short = "L", long = "load-path", parse(from_os_str), number_of_values = 1,
+                help = "directory to look for libraries in, as well as the current one")]
+    load_path: Vec<PathBuf>,
+
+    #[structopt(short = "k", long = "keep-going",
+                help = "carry on running a script after an error, instead of stopping")]
+    keep_going: bool,
+
+    #[structopt(short = "e", long = "eval", number_of_values = 1,
+                help = "evaluate an expression and print the result, instead of running a script")]
+    eval: Vec<String>,
+
+    #[structopt(name = "SCRIPT", help = "script to run, followed by the arguments to pass it")]
+    args: Vec<String>,
 }
 
-const HISTFILE: &str = ".lisprs_hist";
+/// evaluation recurses on the native stack, so it gets a big one of its own
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// how deeply scripts may recurse, which has to fit in `STACK_SIZE` at
+/// `lisprs::STACK_PER_LEVEL` a level
+const MAX_DEPTH: usize = 10_000;
 
 fn main() {
     let opt = Opt::from_args();
@@ -25,42 +68,201 @@ fn main() {
         log::debug(format!("set options: {:?}", opt))
     }
 
+    let runner = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || run(&opt))
+        .unwrap_or_else(|err| {
+            log::error(format!("couldn't start the interpreter: {}", err));
+            process::exit(1);
+        });
+
+    // a panic has already been reported by the time it gets here
+    process::exit(runner.join().unwrap_or(101));
+}
+
+/// do whatever the options say, returning the exit status
+fn run(opt: &Opt) -> i32 {
     let interpreter = Interpreter::default();
+    interpreter.set_limits(Limits {
+        max_depth: Some(MAX_DEPTH),
+        ..Limits::default()
+    });
+    for dir in &opt.load_path {
+        interpreter.add_load_path(dir);
+    }
+
+    if let Some(image) = &opt.load_image {
+        if let Err(err) = interpreter.load_image(image) {
+            log::error(format!("couldn't load image {}: {}", image.display(), err));
+            process::exit(1);
+        }
+    }
+
+    let mut status = match run_program(&interpreter, opt) {
+        Ok(status) => status,
+        Err(err) => lisprs::exit_status(&err).unwrap_or_else(|| {
+            log::error(err);
+            1
+        }),
+    };
+
+    if let Some(image) = &opt.save_image {
+        if let Err(err) = interpreter.save_image(image) {
+            log::error(format!("couldn't save image to {}: {}", image.display(), err));
+            status = status.max(1);
+        }
+    }
+
+    status
+}
+
+/// run the init files, then the script, -e expressions, stdin or the REPL,
+/// returning the exit status. an `(exit)` anywhere comes back as an error.
+fn run_program(interpreter: &Interpreter, opt: &Opt) -> Result<i32, Error> {
     if let Some(initfile) = &opt.initfile {
-        if let Err(why) = interpreter.run_file(initfile) {
-            log::warn(why);
+        run_init(interpreter, initfile)?;
+    }
+
+    // with -e there's no script, so every argument goes to the program
+    let mut command_line = opt.args.clone();
+    if !opt.eval.is_empty() || command_line.is_empty() {
+        command_line.insert(0, "".to_owned());
+    }
+    interpreter.set_command_line(command_line);
+
+    if !opt.eval.is_empty() {
+        eval(interpreter, &opt.eval).map(|_| 0)
+    } else if let Some(script) = opt.args.first() {
+        fs::read_to_string(script)
+            .map_err(|err| format_err!("couldn't read {}: {}", script, err))
+            .and_then(|source| interpreter.run_source(&source, script, opt.keep_going))
+            .map(|_| 0)
+    } else if !atty::is(atty::Stream::Stdin) {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)
+            .map_err(|err| format_err!("couldn't read stdin: {}", err))
+            .and_then(|_| interpreter.run_source(&source, "<stdin>", opt.keep_going))
+            .map(|_| 0)
+    } else {
+        // only interactive sessions get the user's init.scm
+        if let Some(init) = config::config_dir().map(|dir| dir.join("init.scm")) {
+            if init.exists() {
+                run_init(interpreter, &init)?;
+            }
+        }
+
+        Ok(repl(interpreter, opt))
+    }
+}
+
+/// run an init file, only warning about errors in it, except for `(exit)`
+fn run_init(interpreter: &Interpreter, path: &Path) -> Result<(), Error> {
+    match interpreter.run_file(path) {
+        Err(err) if lisprs::exit_status(&err).is_none() => {
+            log::warn(err);
+            Ok(())
         }
+        result => result,
+    }
+}
+
+/// run the expressions given with -e, writing the value of the last one
+fn eval(interpreter: &Interpreter, exprs: &[String]) -> Result<(), Error> {
+    let mut result = None;
+    for expr in exprs {
+        result = Some(interpreter.run(expr.as_str())?);
+    }
+
+    // nil is what display and the like give back, so there's nothing to show
+    match result {
+        Some(Value::Nil) | None => (),
+        Some(result) => println!("{}", result.serialize()),
+    }
+    Ok(())
+}
+
+/// read, evaluate and print until the user has had enough, returning the exit status
+fn repl(interpreter: &Interpreter, opt: &Opt) -> i32 {
+    let config = Config::load();
+    if opt.debug {
+        log::debug(format!("loaded config: {:?}", config));
     }
 
-    let mut rl = Editor::<()>::new();
-    if let Err(err) = rl.load_history(HISTFILE) {
-        log::warn(format!("error opening history file: {}", err));
+    let editor_config = rustyline::Config::builder()
+        .edit_mode(config.edit_mode)
+        .color_mode(config.color_mode())
+        .max_history_size(config.history_size)
+        .build();
+    let mut rl = Editor::<LispHelper>::with_config(editor_config);
+    let command_names: Vec<&str> = COMMANDS.iter().map(|cmd| cmd.name).collect();
+    rl.set_helper(Some(LispHelper::new(interpreter.clone(), &command_names)));
+
+    let histfile = opt.history.clone().or_else(config::history_file);
+    if let Some(histfile) = &histfile {
+        if histfile.exists() {
+            if let Err(err) = rl.load_history(histfile) {
+                log::warn(format!("error opening history file: {}", err));
+            }
+        }
     }
 
-    let prompt = format!("{}lisprs λ{} ", "\x1b[1;94m", log::RESET);
+    let prompt = config.prompt();
+    let mut form = String::new();
+    let mut status = 0;
 
     loop {
-        let input = rl.readline(&prompt);
-
-        match input {
-            // `line` is a ref here to avoid moving into the match arm.
-            // rustc --explain E0008
-            Ok(ref line) if !line.is_empty() => {
-                if line.starts_with('>') && line.len() > 1 {
-                    println!("{}", command(&interpreter, &line[1..], &opt));
-                } else {
-                    rl.add_history_entry(line.as_ref());
-
-                    match interpreter.run(line.clone()) {
-                        Ok(result) => println!("{}", result),
-                        Err(err) => log::error(err),
+        // unfinished forms stay in the editor, so there's only ever the one prompt
+        match rl.readline(&prompt) {
+            Ok(ref line) if form.is_empty() && line.starts_with('>') && line.len() > 1 => {
+                rl.add_history_entry(line.as_str());
+
+                let repl = Repl { interpreter, editor: &rl, opt, config: &config };
+                match commands::run(&repl, &line[1..]) {
+                    Action::Print(output) => println!("{}", output),
+                    Action::Quit(exit_status) => {
+                        status = exit_status;
+                        break;
                     }
                 }
             }
 
+            Ok(line) => {
+                if !form.is_empty() {
+                    form.push('\n');
+                }
+                form.push_str(&line);
+
+                // the helper's validator keeps unfinished forms in the editor, but
+                // when the editor can't use the terminal it reads plain lines, so
+                // keep reading them until the parens balance
+                if form.trim().is_empty() {
+                    form.clear();
+                    continue;
+                } else if lisprs::is_incomplete(&form) {
+                    continue;
+                }
+
+                rl.add_history_entry(form.as_str());
+
+                match interpreter.run(form.clone()) {
+                    Ok(Value::Nil) => (),
+                    Ok(result) => println!("{}", config.show(&result)),
+                    Err(err) => match lisprs::exit_status(&err) {
+                        Some(exit_status) => {
+                            status = exit_status;
+                            break;
+                        }
+                        None => log::error(err),
+                    },
+                }
+
+                form.clear();
+            }
+
             Err(ReadlineError::Interrupted) => {
                 println!("^C");
-                // break;
+                // throw away any unfinished form
+                form.clear();
             }
 
             Err(ReadlineError::Eof) => {
@@ -72,32 +274,19 @@ fn main() {
                 log::error(err);
                 break;
             }
-
-            _ => continue,
         }
     }
 
-    rl.save_history(HISTFILE).unwrap();
-}
+    if let Some(histfile) = &histfile {
+        let saved = match histfile.parent() {
+            Some(dir) => fs::create_dir_all(dir).map_err(ReadlineError::from),
+            None      => Ok(()),
+        }.and_then(|_| rl.save_history(histfile));
 
-fn command(interpreter: &Interpreter, cmd: &str, opt: &Opt) -> String {
-    match cmd {
-        "env" => join(interpreter.env.borrow().vars.keys(), ", "),
-
-        "save" => {
-            if let Some(initfile) = &opt.initfile {
-                match interpreter.save_env(initfile) {
-                    Ok(_) => "".to_owned(),
-                    Err(err) => {
-                        log::warn(err);
-                        "".to_owned()
-                    }
-                }
-            } else {
-                "no initfile set.".to_owned()
-            }
+        if let Err(err) = saved {
+            log::warn(format!("error saving history file: {}", err));
         }
-
-        _ => "invalid command".to_owned(),
     }
+
+    status
 }
@@ -0,0 +1,387 @@
+use failure::Error;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread::{self, ThreadId};
+
+use crate::env::*;
+use crate::errors::RunError;
+use crate::eval;
+use crate::shared::{Lock, Shared};
+use crate::values::Value::{self, *};
+use crate::Interpreter;
+
+// libraries, more or less as in R7RS:
+//
+//   (define-library (math extra)
+//     (export square (rename cube-of cube))
+//     (import (only (math base) double))
+//     (begin
+//       (define square (lambda (n) (* n n)))
+//       (define cube-of (lambda (n) (* n (square n))))))
+//
+//   (import (math extra))                    ; square and cube
+//   (import (prefix (math extra) m:))        ; m:square and m:cube
+//   (import (rename (math extra) (cube c)))  ; square and c
+//   (import (only (math extra) square))      ; just square
+//   (import (except (math extra) square))    ; just cube
+//
+// a library's body runs once, in an Env of its own, and only what it
+// exports can be imported. a library that hasn't been defined yet is looked
+// for in the load path: (math extra) is math/extra.sld, or math/extra.scm,
+// in one of its directories.
+
+/// what a library exports, by the name it's exported as
+type Exports = Shared<Vec<(String, Value)>>;
+
+/// the libraries that have been defined, where to look for more, and the
+/// files that are running
+#[derive(Debug)]
+pub struct Modules {
+    path: Lock<Vec<PathBuf>>,
+    libraries: Lock<HashMap<String, Exports>>,
+    /// libraries being loaded right now, to catch ones that import themselves
+    loading: Lock<Vec<(ThreadId, String)>>,
+    /// the files being run right now, innermost last
+    files: Lock<Vec<(ThreadId, PathBuf)>>,
+}
+
+impl Default for Modules {
+    /// no libraries, and a load path of just the current directory
+    fn default() -> Self {
+        Modules {
+            path: Lock::new(vec![PathBuf::from(".")]),
+            libraries: Lock::default(),
+            loading: Lock::default(),
+            files: Lock::default(),
+        }
+    }
+}
+
+impl Modules {
+    /// the same libraries and load path, which can then be added to without
+    /// adding to these. nothing is running in the copy yet.
+    pub fn copy(&self) -> Modules {
+        Modules {
+            path: Lock::new(self.path.borrow().clone()),
+            libraries: Lock::new(self.libraries.borrow().clone()),
+            loading: Lock::default(),
+            files: Lock::default(),
+        }
+    }
+}
+
+impl Interpreter {
+    /// the directories that libraries are looked for in, in order
+    pub fn load_path(&self) -> Vec<PathBuf> {
+        self.env.borrow().modules.path.borrow().clone()
+    }
+
+    pub fn set_load_path(&self, path: Vec<PathBuf>) {
+        *self.env.borrow().modules.path.borrow_mut() = path;
+    }
+
+    /// look for libraries in `dir` too, after everywhere else
+    pub fn add_load_path<P: Into<PathBuf>>(&self, dir: P) {
+        self.env.borrow().modules.path.borrow_mut().push(dir.into());
+    }
+}
+
+/// run a library definition, and remember what it exports. each library
+/// can only be defined once.
+pub fn define_library(name: &Value, declarations: Vec<Value>, env: &EnvRef) -> Result<(), Error> {
+    let (name, _) = library_name(name)?;
+    if env.borrow().modules.libraries.borrow().contains_key(&name) {
+        return library_error(format!("{} is already defined", name));
+    }
+
+    let mut lib_env = Env::new(None);
+    lib_env.inherit_settings(&env.borrow());
+    let lib_env: EnvRef = Shared::new(Lock::new(lib_env));
+
+    let mut exports = Vec::new();
+    for declaration in declarations {
+        let mut items = match declaration {
+            List(items) => items.into_iter(),
+            other => return library_error(format!("expected a declaration, got {}", other.to_string())),
+        };
+
+        match items.next() {
+            Some(Symbol(ref kind)) if kind == "export" => {
+                for spec in items {
+                    exports.push(export_spec(spec)?);
+                }
+            }
+
+            // evaluated as a form, so that it needs the same capability as
+            // an import anywhere else
+            Some(Symbol(ref kind)) if kind == "import" => {
+                let mut form = vec![Symbol("import".to_owned())];
+                form.extend(items);
+                eval::eval(List(form), lib_env.clone())?;
+            }
+
+            Some(Symbol(ref kind)) if kind == "begin" => {
+                for form in items {
+                    eval::eval(form, lib_env.clone())?;
+                }
+            }
+
+            Some(other) => return library_error(format!("unknown declaration {}", other.to_string())),
+            None => return library_error("empty declaration"),
+        }
+    }
+
+    let mut values = Vec::new();
+    for (internal, external) in exports {
+        match lib_env.borrow().lookup(&internal) {
+            Some(value) => values.push((external, value)),
+            None => return library_error(format!("{} exports {}, which it doesn't define", name, internal)),
+        }
+    }
+
+    env.borrow().modules.libraries.borrow_mut().insert(name, Shared::new(values));
+    Ok(())
+}
+
+/// bring what an import set names into `env`
+pub fn import(set: &Value, env: &EnvRef) -> Result<(), Error> {
+    let (library, bindings) = resolve(set, env)?;
+
+    // all or nothing: check every name before importing any of them
+    let mut env = env.borrow_mut();
+    for (name, _) in &bindings {
+        env.check_import(name, &library)?;
+    }
+    for (name, value) in bindings {
+        env.import(&name, value, &library)?;
+    }
+
+    Ok(())
+}
+
+/// work out which library an import set is from, and the names and values
+/// it imports
+fn resolve(set: &Value, env: &EnvRef) -> Result<(String, Vec<(String, Value)>), Error> {
+    let items = match set {
+        List(items) if !items.is_empty() => items,
+        _ => return import_error(format!("expected an import set, got {}", set.to_string())),
+    };
+
+    let modifier = match (&items[0], items.get(1)) {
+        (Symbol(modifier), Some(List(_))) => modifier.as_str(),
+        _ => "",
+    };
+
+    // a plain library name
+    if !["only", "except", "prefix", "rename"].contains(&modifier) {
+        let (name, parts) = library_name(set)?;
+        let exports = load(&name, &parts, env)?;
+        return Ok((name, exports.to_vec()));
+    }
+
+    let (library, bindings) = resolve(&items[1], env)?;
+    let args = &items[2..];
+
+    let check_exported = |name: &str| -> Result<(), Error> {
+        if bindings.iter().any(|(exported, _)| exported == name) {
+            Ok(())
+        } else {
+            import_error(format!("{} doesn't export {}", library, name))
+        }
+    };
+
+    let bindings = match modifier {
+        "only" => {
+            let names = args.iter().map(symbol).collect::<Result<Vec<_>, _>>()?;
+            for name in &names {
+                check_exported(name)?;
+            }
+            bindings.into_iter().filter(|(name, _)| names.contains(name)).collect()
+        }
+
+        "except" => {
+            let names = args.iter().map(symbol).collect::<Result<Vec<_>, _>>()?;
+            for name in &names {
+                check_exported(name)?;
+            }
+            bindings.into_iter().filter(|(name, _)| !names.contains(name)).collect()
+        }
+
+        "prefix" => {
+            let prefix = match args {
+                [prefix] => symbol(prefix)?,
+                _ => return import_error("prefix takes one prefix"),
+            };
+            bindings.into_iter().map(|(name, value)| (format!("{}{}", prefix, name), value)).collect()
+        }
+
+        _ => {
+            let mut renames = HashMap::new();
+            for pair in args {
+                match pair {
+                    List(pair) if pair.len() == 2 => {
+                        let from = symbol(&pair[0])?;
+                        check_exported(&from)?;
+                        renames.insert(from, symbol(&pair[1])?);
+                    }
+                    _ => return import_error(format!("expected (<from> <to>), got {}", pair.to_string())),
+                }
+            }
+            bindings.into_iter()
+                .map(|(name, value)| (renames.remove(&name).unwrap_or(name), value))
+                .collect()
+        }
+    };
+
+    Ok((library, bindings))
+}
+
+/// the exports of a library, loading it from the load path if it hasn't
+/// been defined yet
+fn load(name: &str, parts: &[String], env: &EnvRef) -> Result<Exports, Error> {
+    let modules = env.borrow().modules.clone();
+
+    if let Some(exports) = modules.libraries.borrow().get(name) {
+        return Ok(exports.clone());
+    }
+
+    let loading = (thread::current().id(), name.to_owned());
+    if modules.loading.borrow().contains(&loading) {
+        return import_error(format!("{} imports itself", name));
+    }
+
+    let file = modules.find(parts).ok_or_else(|| RunError::LibraryNotFound(name.to_owned()))?;
+    let source = fs::read_to_string(&file)?;
+
+    // the file runs in an Env of its own, so only define-library in it
+    // has any effect outside of it
+    let mut file_env = Env::new(None);
+    file_env.inherit_settings(&env.borrow());
+    let loader = Interpreter { env: Shared::new(Lock::new(file_env)) };
+
+    modules.loading.borrow_mut().push(loading.clone());
+    let result = loader.run_source(&source, &file.display().to_string(), false);
+    modules.loading.borrow_mut().retain(|other| *other != loading);
+    result?;
+
+    let exports = modules.libraries.borrow().get(name).cloned();
+    match exports {
+        Some(exports) => Ok(exports),
+        None => import_error(format!("{} doesn't define {}", file.display(), name)),
+    }
+}
+
+impl Modules {
+    /// note that a file has started running. it's an error if it's already
+    /// running, since it would only end up running itself again.
+    pub fn enter_file(&self, path: &Path) -> Result<(), Error> {
+        let id = thread::current().id();
+
+        // names that aren't real files, like <stdin>, can't be circular
+        let path = match fs::canonicalize(path) {
+            Ok(path) => {
+                if self.files.borrow().contains(&(id, path.clone())) {
+                    return Err(RunError::CircularLoad(path.display().to_string()).into());
+                }
+                path
+            }
+            Err(_) => path.to_owned(),
+        };
+
+        self.files.borrow_mut().push((id, path));
+        Ok(())
+    }
+
+    /// note that the innermost running file has finished
+    pub fn leave_file(&self) {
+        let id = thread::current().id();
+        let mut files = self.files.borrow_mut();
+        if let Some(i) = files.iter().rposition(|(thread, _)| *thread == id) {
+            files.remove(i);
+        }
+    }
+
+    /// a path relative to the file that's running, or to the current
+    /// directory if there isn't one
+    pub fn relative_path(&self, path: &str) -> PathBuf {
+        let id = thread::current().id();
+        let files = self.files.borrow();
+
+        match files.iter().rev().find(|(thread, _)| *thread == id) {
+            Some((_, file)) => file.parent().unwrap_or_else(|| Path::new("")).join(path),
+            None => PathBuf::from(path),
+        }
+    }
+
+    /// the first file in the load path that could hold a library
+    fn find(&self, parts: &[String]) -> Option<PathBuf> {
+        let (last, dirs) = parts.split_last()?;
+
+        for root in self.path.borrow().iter() {
+            let mut dir = root.clone();
+            dir.extend(dirs);
+
+            for extension in &["sld", "scm"] {
+                let file = dir.join(format!("{}.{}", last, extension));
+                if file.is_file() {
+                    return Some(file);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// a library's name as it's printed, e.g. `(math extra)`, and its parts
+fn library_name(value: &Value) -> Result<(String, Vec<String>), Error> {
+    let parts = match value {
+        List(parts) if !parts.is_empty() => parts.iter()
+            .map(|part| match part {
+                Symbol(s) => Ok(s.clone()),
+                Integer(n) => Ok(n.to_string()),
+                _ => library_error(format!("invalid library name {}", value.to_string())),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => return library_error(format!("invalid library name {}", value.to_string())),
+    };
+
+    Ok((format!("({})", parts.join(" ")), parts))
+}
+
+/// `name` or `(rename <internal> <external>)`, as (internal, external)
+fn export_spec(spec: Value) -> Result<(String, String), Error> {
+    match spec {
+        Symbol(name) => Ok((name.clone(), name)),
+        List(ref items) if items.len() == 3 && items[0] == Symbol("rename".to_owned()) => {
+            Ok((symbol(&items[1])?, symbol(&items[2])?))
+        }
+        other => library_error(format!("invalid export {}", other.to_string())),
+    }
+}
+
+fn symbol(value: &Value) -> Result<String, Error> {
+    match value {
+        Symbol(s) => Ok(s.clone()),
+        _ => Err(RunError::TypeError {
+            name: "import".to_owned(),
+            expected: "Symbol".to_owned(),
+            got: value.get_type(),
+        }.into()),
+    }
+}
+
+fn library_error<T, S: Into<String>>(msg: S) -> Result<T, Error> {
+    Err(RunError::ProcError {
+        name: "define-library".to_owned(),
+        msg: msg.into(),
+    }.into())
+}
+
+fn import_error<T, S: Into<String>>(msg: S) -> Result<T, Error> {
+    Err(RunError::ProcError {
+        name: "import".to_owned(),
+        msg: msg.into(),
+    }.into())
+}
@@ -1,4 +1,7 @@
 use failure::Error;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use std::str::Chars;
 
 use crate::errors::ParseError;
 use crate::values::Value::{self, *};
@@ -17,9 +20,15 @@ pub fn tokenize(string: &str) -> Vec<Token> {
 
     let mut escaped_state = false;
     let mut string_state = false;
+    let mut comment_state = false;
 
     for c in string.chars() {
-        if !string_state {
+        if comment_state {
+            // comments run to the end of the line
+            if c == '\n' {
+                comment_state = false;
+            }
+        } else if !string_state {
             match c {
                 '(' => {
                     push_item(&mut item, &mut tokens);
@@ -31,7 +40,7 @@ pub fn tokenize(string: &str) -> Vec<Token> {
                     tokens.push(Token::RightParen);
                 }
 
-                ' ' => push_item(&mut item, &mut tokens),
+                c if c.is_whitespace() => push_item(&mut item, &mut tokens),
 
                 '"' => {
                     string_state = true;
@@ -40,7 +49,7 @@ pub fn tokenize(string: &str) -> Vec<Token> {
 
                 ';' => {
                     push_item(&mut item, &mut tokens);
-                    return tokens;
+                    comment_state = true;
                 }
 
                 _ => item.push(c),
@@ -68,6 +77,42 @@ pub fn tokenize(string: &str) -> Vec<Token> {
     tokens
 }
 
+/// check whether some code is an unfinished expression, i.e. it has
+/// unclosed parentheses or an unterminated string
+pub fn is_incomplete(string: &str) -> bool {
+    let mut depth = 0;
+
+    let mut escaped_state = false;
+    let mut string_state = false;
+    let mut comment_state = false;
+
+    for c in string.chars() {
+        if comment_state {
+            if c == '\n' {
+                comment_state = false;
+            }
+        } else if string_state {
+            if escaped_state {
+                escaped_state = false;
+            } else if c == '\\' {
+                escaped_state = true;
+            } else if c == '"' {
+                string_state = false;
+            }
+        } else {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                '"' => string_state = true,
+                ';' => comment_state = true,
+                _ => (),
+            }
+        }
+    }
+
+    string_state || depth > 0
+}
+
 fn push_item(item: &mut String, tokens: &mut Vec<Token>) {
     if !item.is_empty() {
         tokens.push(Token::Item(item.clone()));
@@ -78,6 +123,9 @@ fn push_item(item: &mut String, tokens: &mut Vec<Token>) {
 impl Value {
     /// parse a Vec of tokens into a structured s-expression
     pub fn from_tokens(tokens: &mut Vec<Token>) -> Result<Value, Error> {
+        if tokens.is_empty() {
+            return Err(ParseError::MismatchedParens.into());
+        }
         let token = tokens.remove(0);
 
         match token {
@@ -86,47 +134,59 @@ impl Value {
             }
 
             Token::LeftParen => {
-                let mut list: Vec<Value> = Vec::new();
-
-                while tokens[0] != Token::RightParen {
-                    list.push(Value::from_tokens(tokens)?);
-                }
-
-                tokens.remove(0);
-                Ok(List(list))
+                Ok(List(Value::list_from_tokens(tokens)?))
             }
 
             Token::Item(s) => {
                 // handle quoted lists: '(<expr> <expr> ...)
                 if s.as_str() == "'" {
-                    if tokens.remove(0) != Token::LeftParen {
+                    if tokens.first() != Some(&Token::LeftParen) {
                         return Err(ParseError::ErroneousToken("'".to_string()))?;
                     }
-
-                    let mut list: Vec<Value> = Vec::new();
-
-                    while tokens[0] != Token::RightParen {
-                        list.push(Value::from_tokens(tokens)?);
-                    }
-
                     tokens.remove(0);
+
+                    let list = Value::list_from_tokens(tokens)?;
                     // this becomes: (quote (<expr> <expr> ...))
                     Ok(List(vec![Symbol("quote".to_owned()), List(list)]))
                 } else {
-                    Ok(Value::atomize(s))
+                    Value::atomize(s)
                 }
             }
         }
     }
 
+    /// parse the items of a list, up to and including its right paren
+    fn list_from_tokens(tokens: &mut Vec<Token>) -> Result<Vec<Value>, Error> {
+        let mut list: Vec<Value> = Vec::new();
+
+        loop {
+            match tokens.first() {
+                Some(Token::RightParen) => break,
+                Some(_) => list.push(Value::from_tokens(tokens)?),
+                None => return Err(ParseError::MismatchedParens.into()),
+            }
+        }
+
+        tokens.remove(0);
+        Ok(list)
+    }
+
     /// parse an item into an atom
-    fn atomize(mut token: String) -> Value {
-        if token.starts_with('"') && token.ends_with('"') && token.len() > 1 {
+    fn atomize(mut token: String) -> Result<Value, Error> {
+        let atom = if token.starts_with('"') && token.ends_with('"') && token.len() > 1 {
             token.pop();
             token.remove(0);
-            Str(token)
+            Str(unescape(&token)?)
+        } else if let Some(n) = parse_radix(&token)? {
+            n
         } else if let Ok(n) = token.parse::<i64>() {
             Integer(n)
+        } else if let Ok(n) = token.parse::<BigInt>() {
+            Bignum(n)
+        } else if let Ok(n) = token.parse::<BigRational>() {
+            Value::from_rational(n)
+        } else if let Some(n) = special_float(&token) {
+            Float(n)
         } else if let Ok(n) = token.parse::<f64>() {
             Float(n)
         } else if &token == "#t" {
@@ -137,6 +197,111 @@ impl Value {
             Nil
         } else {
             Symbol(token)
+        };
+
+        Ok(atom)
+    }
+}
+
+/// parse an integer literal with a radix prefix, like #xff, #b1010 or #o17.
+/// it's None without a prefix, and an error with digits the radix doesn't have.
+fn parse_radix(token: &str) -> Result<Option<Value>, Error> {
+    const PREFIXES: &[(&str, u32)] = &[
+        ("#x", 16), ("#X", 16), ("#o", 8), ("#O", 8), ("#b", 2), ("#B", 2), ("#d", 10), ("#D", 10),
+    ];
+
+    let found = PREFIXES.iter()
+        .find_map(|(prefix, radix)| token.strip_prefix(prefix).map(|digits| (digits, *radix)));
+    let (digits, radix) = match found {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+
+    match BigInt::parse_bytes(digits.as_bytes(), radix) {
+        Some(n) => Ok(Some(Value::from_bigint(n))),
+        None => Err(ParseError::ErroneousToken(token.to_owned()).into()),
+    }
+}
+
+/// the scheme spellings of the floats that aren't numbers: +inf.0, -inf.0 and +nan.0
+fn special_float(token: &str) -> Option<f64> {
+    match token {
+        "+inf.0" => Some(f64::INFINITY),
+        "-inf.0" => Some(f64::NEG_INFINITY),
+        "+nan.0" | "-nan.0" => Some(f64::NAN),
+        _ => None,
+    }
+}
+
+/// decode the escape sequences in the body of a string literal
+/// supported: `\n` `\t` `\r` `\\` `\"` `\x<hex>;` `\u{<hex>}`
+fn unescape(raw: &str) -> Result<String, Error> {
+    let mut string = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n')  => string.push('\n'),
+            Some('t')  => string.push('\t'),
+            Some('r')  => string.push('\r'),
+            Some('\\') => string.push('\\'),
+            Some('"')  => string.push('"'),
+
+            Some('x') => match hex_char(&mut chars, ';') {
+                Some(c) => string.push(c),
+                None    => return Err(ParseError::InvalidEscape("\\x".to_owned()).into()),
+            },
+
+            Some('u') => match chars.next() {
+                Some('{') => match hex_char(&mut chars, '}') {
+                    Some(c) => string.push(c),
+                    None    => return Err(ParseError::InvalidEscape("\\u".to_owned()).into()),
+                },
+                _ => return Err(ParseError::InvalidEscape("\\u".to_owned()).into()),
+            },
+
+            Some(c) => return Err(ParseError::InvalidEscape(format!("\\{}", c)).into()),
+            None    => return Err(ParseError::InvalidEscape("\\".to_owned()).into()),
+        }
+    }
+
+    Ok(string)
+}
+
+/// read hex digits up to `terminator` and turn them into the char they encode
+fn hex_char(chars: &mut Chars, terminator: char) -> Option<char> {
+    let mut hex = String::new();
+
+    for c in chars {
+        if c == terminator {
+            return u32::from_str_radix(&hex, 16).ok().and_then(std::char::from_u32);
         }
+        hex.push(c);
     }
+
+    None
+}
+
+/// escape a string so that reading it back in with `unescape` gives the same string
+pub fn escape(string: &str) -> String {
+    let mut escaped = String::with_capacity(string.len());
+
+    for c in string.chars() {
+        match c {
+            '\n'  => escaped.push_str("\\n"),
+            '\t'  => escaped.push_str("\\t"),
+            '\r'  => escaped.push_str("\\r"),
+            '\\'  => escaped.push_str("\\\\"),
+            '"'   => escaped.push_str("\\\""),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:x};", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
 }
@@ -0,0 +1,368 @@
+use failure::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+use crate::errors::{ParseError, RunError};
+use crate::shared::{Lock, MaybeSync, Shared};
+use crate::Interpreter;
+
+// ports are where input comes from and where output goes: files, strings,
+// or the process's stdin, stdout and stderr. a port is a handle, so copies
+// of it share the same stream, and closing one closes them all.
+//
+// display, write and read use the interpreter's current ports unless
+// they're given one. embedders can swap those for their own, e.g. a buffer
+// to collect everything a script prints.
+
+#[cfg(not(feature = "sync"))]
+type Reader = Box<dyn BufRead>;
+#[cfg(feature = "sync")]
+type Reader = Box<dyn BufRead + Send + Sync>;
+
+#[cfg(not(feature = "sync"))]
+type Writer = Box<dyn Write>;
+#[cfg(feature = "sync")]
+type Writer = Box<dyn Write + Send + Sync>;
+
+enum Stream {
+    Input {
+        reader: Reader,
+        /// a char that's been peeked at, but not read yet
+        peeked: Option<char>,
+    },
+    Output(Writer),
+    /// output that's kept in memory
+    Buffer(Vec<u8>),
+    Closed,
+}
+
+/// somewhere to read from or write to
+#[derive(Clone)]
+pub struct Port {
+    name: String,
+    stream: Shared<Lock<Stream>>,
+}
+
+impl Port {
+    fn new(name: &str, stream: Stream) -> Port {
+        Port {
+            name: name.to_owned(),
+            stream: Shared::new(Lock::new(stream)),
+        }
+    }
+
+    /// an input port that reads from `reader`
+    pub fn input<R>(name: &str, reader: R) -> Port
+    where
+        R: BufRead + MaybeSync + 'static,
+    {
+        Port::new(name, Stream::Input { reader: Box::new(reader), peeked: None })
+    }
+
+    /// an output port that writes to `writer`
+    pub fn output<W>(name: &str, writer: W) -> Port
+    where
+        W: Write + MaybeSync + 'static,
+    {
+        Port::new(name, Stream::Output(Box::new(writer)))
+    }
+
+    /// an output port that keeps what's written to it, see `contents`
+    pub fn buffer() -> Port {
+        Port::new("<string>", Stream::Buffer(Vec::new()))
+    }
+
+    /// an input port that reads from a string
+    pub fn from_string(s: &str) -> Port {
+        Port::input("<string>", io::Cursor::new(s.as_bytes().to_vec()))
+    }
+
+    pub fn open_input_file<P: AsRef<Path>>(path: P) -> Result<Port, Error> {
+        let file = File::open(&path)?;
+        Ok(Port::input(&path.as_ref().to_string_lossy(), BufReader::new(file)))
+    }
+
+    pub fn open_output_file<P: AsRef<Path>>(path: P) -> Result<Port, Error> {
+        let file = File::create(&path)?;
+        Ok(Port::output(&path.as_ref().to_string_lossy(), file))
+    }
+
+    pub fn stdin() -> Port {
+        Port::input("<stdin>", BufReader::new(io::stdin()))
+    }
+
+    pub fn stdout() -> Port {
+        Port::output("<stdout>", io::stdout())
+    }
+
+    pub fn stderr() -> Port {
+        Port::output("<stderr>", io::stderr())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// everything written to a port made by `buffer` so far
+    pub fn contents(&self) -> Option<String> {
+        match &*self.stream.borrow() {
+            Stream::Buffer(buffer) => Some(String::from_utf8_lossy(buffer).into_owned()),
+            _ => None,
+        }
+    }
+
+    /// are these the same port?
+    pub fn same(&self, other: &Port) -> bool {
+        Shared::ptr_eq(&self.stream, &other.stream)
+    }
+
+    /// stop using the port, closing its file if it has one. what a buffer
+    /// holds can still be got at afterwards.
+    pub fn close(&self) {
+        let mut stream = self.stream.borrow_mut();
+        if let Stream::Output(writer) = &mut *stream {
+            let _ = writer.flush();
+        }
+        if !matches!(*stream, Stream::Buffer(_)) {
+            *stream = Stream::Closed;
+        }
+    }
+
+    // {{{ output
+    pub fn write_str(&self, s: &str) -> Result<(), Error> {
+        match &mut *self.stream.borrow_mut() {
+            // flushed straight away, so that output and input are interleaved
+            // the way they were asked for
+            Stream::Output(writer) => {
+                writer.write_all(s.as_bytes())?;
+                writer.flush()?;
+            }
+            Stream::Buffer(buffer) => buffer.extend_from_slice(s.as_bytes()),
+            Stream::Input { .. } => return self.error("not an output port"),
+            Stream::Closed => return self.error("port is closed"),
+        }
+
+        Ok(())
+    }
+    // }}}
+
+    // {{{ input
+    /// the next char, or None at the end of the input
+    pub fn read_char(&self) -> Result<Option<char>, Error> {
+        let mut stream = self.stream.borrow_mut();
+        let (reader, peeked) = self.reader(&mut stream)?;
+
+        match peeked.take() {
+            Some(c) => Ok(Some(c)),
+            None => next_char(reader),
+        }
+    }
+
+    /// the next char, without reading it
+    pub fn peek_char(&self) -> Result<Option<char>, Error> {
+        let mut stream = self.stream.borrow_mut();
+        let (reader, peeked) = self.reader(&mut stream)?;
+
+        if peeked.is_none() {
+            *peeked = next_char(reader)?;
+        }
+        Ok(*peeked)
+    }
+
+    /// the rest of the line, without the line ending, or None at the end
+    /// of the input
+    pub fn read_line(&self) -> Result<Option<String>, Error> {
+        let mut stream = self.stream.borrow_mut();
+        let (reader, peeked) = self.reader(&mut stream)?;
+
+        let mut line = String::new();
+        match peeked.take() {
+            Some('\n') => return Ok(Some(line)),
+            Some(c) => line.push(c),
+            None => (),
+        }
+
+        if reader.read_line(&mut line)? == 0 && line.is_empty() {
+            return Ok(None);
+        }
+
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    /// the source of the next whole expression, or None at the end of the
+    /// input. it still needs parsing.
+    pub fn read_datum(&self) -> Result<Option<String>, Error> {
+        // skip whitespace and comments
+        loop {
+            match self.peek_char()? {
+                None => return Ok(None),
+                Some(c) if c.is_whitespace() => {
+                    self.read_char()?;
+                }
+                Some(';') => {
+                    self.read_line()?;
+                }
+                Some(_) => break,
+            }
+        }
+
+        let mut datum = String::new();
+        let mut depth = 0;
+        let mut string_state = false;
+        let mut escaped_state = false;
+
+        loop {
+            // a quote in front of a datum is part of it, even with space after it
+            let prefix = datum.chars().all(|c| c == '\'' || c.is_whitespace());
+
+            let c = match self.peek_char()? {
+                Some(c) => c,
+                None if string_state || depth > 0 => return Err(ParseError::MismatchedParens.into()),
+                None => break,
+            };
+
+            if string_state {
+                if escaped_state {
+                    escaped_state = false;
+                } else if c == '\\' {
+                    escaped_state = true;
+                } else if c == '"' {
+                    string_state = false;
+                }
+            } else {
+                match c {
+                    '(' if depth == 0 && !prefix => break,
+                    '(' => depth += 1,
+                    ')' if datum.is_empty() => {
+                        self.read_char()?;
+                        return Err(ParseError::MismatchedParens.into());
+                    }
+                    ')' if depth == 0 => break,
+                    ')' => depth -= 1,
+                    '"' if depth == 0 && !prefix => break,
+                    '"' => string_state = true,
+                    ';' if depth == 0 && !prefix => break,
+                    ';' => {
+                        // a comment inside a list
+                        self.read_line()?;
+                        datum.push('\n');
+                        continue;
+                    }
+                    c if c.is_whitespace() && depth == 0 && !prefix => break,
+                    _ => (),
+                }
+            }
+
+            self.read_char()?;
+            datum.push(c);
+
+            // a list or a string is finished as soon as it's closed
+            if depth == 0 && !string_state && (c == ')' || c == '"') {
+                break;
+            }
+        }
+
+        Ok(Some(datum))
+    }
+
+    fn reader<'a>(&self, stream: &'a mut Stream) -> Result<(&'a mut Reader, &'a mut Option<char>), Error> {
+        match stream {
+            Stream::Input { reader, peeked } => Ok((reader, peeked)),
+            Stream::Closed => self.error("port is closed"),
+            _ => self.error("not an input port"),
+        }
+    }
+    // }}}
+
+    fn error<T>(&self, msg: &str) -> Result<T, Error> {
+        Err(RunError::ProcError {
+            name: self.name.clone(),
+            msg: msg.to_owned(),
+        }.into())
+    }
+}
+
+/// read one utf-8 encoded char
+fn next_char(reader: &mut Reader) -> Result<Option<char>, Error> {
+    let mut bytes = [0; 4];
+    if reader.read(&mut bytes[..1])? == 0 {
+        return Ok(None);
+    }
+
+    let len = match bytes[0] {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => 4,
+    };
+    reader.read_exact(&mut bytes[1..len])?;
+
+    let c = std::str::from_utf8(&bytes[..len])
+        .ok()
+        .and_then(|s| s.chars().next())
+        .unwrap_or(char::REPLACEMENT_CHARACTER);
+    Ok(Some(c))
+}
+
+impl fmt::Debug for Port {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Port({})", self.name)
+    }
+}
+
+/// the ports that input and output go to when no other port is given
+#[derive(Debug)]
+pub struct CurrentPorts {
+    pub input: Lock<Port>,
+    pub output: Lock<Port>,
+    pub error: Lock<Port>,
+}
+
+impl CurrentPorts {
+    /// the same ports, which can then be changed without changing these
+    pub fn copy(&self) -> CurrentPorts {
+        CurrentPorts {
+            input: Lock::new(self.input.borrow().clone()),
+            output: Lock::new(self.output.borrow().clone()),
+            error: Lock::new(self.error.borrow().clone()),
+        }
+    }
+}
+
+impl Default for CurrentPorts {
+    /// the process's stdin, stdout and stderr
+    fn default() -> Self {
+        CurrentPorts {
+            input: Lock::new(Port::stdin()),
+            output: Lock::new(Port::stdout()),
+            error: Lock::new(Port::stderr()),
+        }
+    }
+}
+
+impl Interpreter {
+    /// where `read`, `read-line` and `read-char` read from when they're not
+    /// given a port. it's stdin to begin with.
+    pub fn set_input_port(&self, port: Port) {
+        *self.env.borrow().ports.input.borrow_mut() = port;
+    }
+
+    /// where `display`, `write` and `newline` go when they're not given a
+    /// port. it's stdout to begin with.
+    pub fn set_output_port(&self, port: Port) {
+        *self.env.borrow().ports.output.borrow_mut() = port;
+    }
+
+    /// where `(current-error-port)` goes. it's stderr to begin with.
+    pub fn set_error_port(&self, port: Port) {
+        *self.env.borrow().ports.error.borrow_mut() = port;
+    }
+}
@@ -0,0 +1,210 @@
+use crate::parser;
+use crate::values::Value::{self, *};
+
+// one printer for both of the ways a value can be turned into text:
+//
+//   display  is for people: strings print as they are, wherever they are,
+//            so (display '("a" b)) prints (a b)
+//   write    is for `read`: strings print in quotes, with escapes, so
+//            (write '("a" b)) prints ("a" b), which reads back in as the
+//            same list
+//
+// everything else prints the same either way. values are trees, so there
+// are no cycles to follow: a list can't hold itself, and a procedure prints
+// its body, not the Env it closes over. they can still be nested deeply
+// enough to overflow the stack, so lists past `MAX_DEPTH` print as `...`.
+//
+// `pretty` prints the same text, but breaks lists that are too wide over
+// several lines, indented the way scheme code usually is:
+//
+//   (define (fact n)                  ; define, lambda and let put their
+//     (if (= n 0)                     ; body on the lines after, indented
+//         1                           ; by two
+//         (* n (fact (- n 1)))))      ; calls line up their arguments
+//
+//   (1 2 3 4 5 6 7 8 9 10 11 12 13    ; and lists of atoms are filled, like
+//    14 15 16)                        ; text
+
+/// how to print strings, see above
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Display,
+    Write,
+}
+
+/// how deeply lists are printed before the rest is left out
+pub const MAX_DEPTH: usize = 1000;
+
+/// how many columns `pretty` keeps to unless it's told otherwise
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// forms whose first argument stays on their first line, with the rest of
+/// them indented by two on the lines after it
+const BODY_FORMS: &[&str] = &[
+    "define", "lambda", "let", "let*", "letrec", "letrec*", "define-library",
+];
+
+pub fn print(value: &Value, style: Style) -> String {
+    let mut out = String::new();
+    print_into(&mut out, value, style, 0);
+    out
+}
+
+/// print a value over as many lines as it takes to keep them within `width`
+/// columns, where that's possible
+pub fn pretty(value: &Value, style: Style, width: usize) -> String {
+    let mut out = String::new();
+    pretty_into(&mut out, value, style, width, 0, 0);
+    out
+}
+
+/// `after` is how many parens will close on the same line after the value
+fn pretty_into(out: &mut String, value: &Value, style: Style, width: usize, depth: usize, after: usize) {
+    let mut flat = String::new();
+    print_into(&mut flat, value, style, depth);
+    if column(out) + flat.chars().count() + after <= width || depth >= MAX_DEPTH {
+        out.push_str(&flat);
+        return;
+    }
+
+    match value {
+        List(items) if !items.is_empty() => pretty_list(out, items, style, width, depth, after),
+
+        // printed as the lambda form it's made from
+        Proc(p) => {
+            let params = p.params.iter().cloned().map(Symbol).collect();
+            let lambda = List(vec![Symbol("lambda".to_owned()), List(params), p.body.clone()]);
+            pretty_into(out, &lambda, style, width, depth, after);
+        }
+
+        _ => out.push_str(&flat),
+    }
+}
+
+fn pretty_list(out: &mut String, items: &[Value], style: Style, width: usize, depth: usize, after: usize) {
+    let start = column(out);
+    out.push('(');
+
+    if items.iter().all(|item| !matches!(item, List(_) | Proc(_))) {
+        return fill(out, items, style, width, depth, start + 1, after);
+    }
+
+    // how many items go on the first line, and where the others start
+    let (inline, indent) = match &items[0] {
+        Symbol(s) if s == "let" && matches!(items.get(1), Some(Symbol(_))) => (3, start + 2),
+        Symbol(s) if BODY_FORMS.contains(&s.as_str()) => (2, start + 2),
+        Symbol(s) => (2, start + s.chars().count() + 2),
+        _ => (1, start + 1),
+    };
+
+    for (i, item) in items.iter().enumerate() {
+        if i >= inline {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent));
+        } else if i > 0 {
+            out.push(' ');
+        }
+        let last = i + 1 == items.len();
+        pretty_into(out, item, style, width, depth + 1, if last { after + 1 } else { 0 });
+    }
+    out.push(')');
+}
+
+/// print atoms one after another, starting a new line when one won't fit
+fn fill(
+    out: &mut String,
+    items: &[Value],
+    style: Style,
+    width: usize,
+    depth: usize,
+    indent: usize,
+    after: usize,
+) {
+    for (i, item) in items.iter().enumerate() {
+        let mut atom = String::new();
+        print_into(&mut atom, item, style, depth + 1);
+
+        // the last one needs room for the parens after it too
+        let room = atom.chars().count() + if i + 1 == items.len() { after + 1 } else { 0 };
+        if i > 0 && column(out) + 1 + room > width {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent));
+        } else if i > 0 {
+            out.push(' ');
+        }
+        out.push_str(&atom);
+    }
+    out.push(')');
+}
+
+/// whether printing a value would leave some of it out as `...`
+pub fn too_deep(value: &Value) -> bool {
+    exceeds_depth(value, 0)
+}
+
+fn exceeds_depth(value: &Value, depth: usize) -> bool {
+    match value {
+        List(_) | Proc(_) if depth >= MAX_DEPTH => true,
+        List(list) => list.iter().any(|item| exceeds_depth(item, depth + 1)),
+        Proc(p) => exceeds_depth(&p.body, depth + 1),
+        _ => false,
+    }
+}
+
+/// how far along its last line some output has got
+fn column(out: &str) -> usize {
+    out.rsplit('\n').next().unwrap_or("").chars().count()
+}
+
+fn print_into(out: &mut String, value: &Value, style: Style, depth: usize) {
+    match value {
+        Str(s) if style == Style::Write => {
+            out.push('"');
+            out.push_str(&parser::escape(s));
+            out.push('"');
+        }
+        Str(s) | Symbol(s) => out.push_str(s),
+
+        Integer(n)  => out.push_str(&n.to_string()),
+        Bignum(n)   => out.push_str(&n.to_string()),
+        Rational(n) => out.push_str(&n.to_string()),
+        Float(n)    => out.push_str(&float(*n)),
+        Bool(true)  => out.push_str("#t"),
+        Bool(false) => out.push_str("#f"),
+        Nil         => out.push_str("nil"),
+
+        List(_) | Proc(_) if depth >= MAX_DEPTH => out.push_str("..."),
+
+        List(list) => {
+            out.push('(');
+            for (i, item) in list.iter().enumerate() {
+                if i > 0 {
+                    out.push(' ');
+                }
+                print_into(out, item, style, depth + 1);
+            }
+            out.push(')');
+        }
+
+        Proc(p) => {
+            out.push_str("(lambda (");
+            out.push_str(&p.params.join(" "));
+            out.push_str(") ");
+            print_into(out, &p.body, style, depth + 1);
+            out.push(')');
+        }
+
+        Native(f) => out.push_str(&format!("<native {}>", f.name)),
+        Port(p)   => out.push_str(&format!("<port {}>", p.name())),
+        Eof       => out.push_str("<eof>"),
+    }
+}
+
+/// a float that reads back in as a float: 1.0 rather than 1
+fn float(n: f64) -> String {
+    if n.is_finite() && n.fract() == 0.0 {
+        format!("{:.1}", n)
+    } else {
+        n.to_string()
+    }
+}
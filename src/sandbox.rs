@@ -0,0 +1,130 @@
+use std::collections::HashSet;
+
+use crate::env::*;
+use crate::limits::Limits;
+use crate::shared::{Lock, Shared};
+use crate::values::Truthiness;
+use crate::Interpreter;
+
+/// a group of builtins that an interpreter can be given access to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// defining things, control flow, quoting, lists and equality
+    Core,
+    /// arithmetic, comparisons and everything else on numbers
+    Math,
+    /// building and changing strings
+    Strings,
+    /// reading and writing files
+    Io,
+    /// the process and its surroundings, e.g. `exit`
+    Os,
+}
+
+impl Capability {
+    pub const ALL: &'static [Capability] = &[
+        Capability::Core,
+        Capability::Math,
+        Capability::Strings,
+        Capability::Io,
+        Capability::Os,
+    ];
+}
+
+/// which builtins an interpreter is allowed to call
+#[derive(Debug, Clone)]
+pub struct Capabilities {
+    granted: HashSet<Capability>,
+    denied: HashSet<String>,
+}
+
+impl Capabilities {
+    /// may the builtin `name`, from the group `group`, be called?
+    pub fn allows(&self, name: &str, group: Capability) -> bool {
+        self.granted.contains(&group) && !self.denied.contains(name)
+    }
+}
+
+impl Default for Capabilities {
+    /// everything is allowed
+    fn default() -> Self {
+        Capabilities {
+            granted: Capability::ALL.iter().cloned().collect(),
+            denied: HashSet::new(),
+        }
+    }
+}
+
+/// sets up an `Interpreter` with only some of the builtins available, for
+/// running code that can't be trusted with everything. only `Core` is granted
+/// to begin with.
+#[derive(Debug, Clone)]
+pub struct InterpreterBuilder {
+    capabilities: Capabilities,
+    truthiness: Truthiness,
+    limits: Limits,
+}
+
+impl InterpreterBuilder {
+    pub fn new() -> InterpreterBuilder {
+        InterpreterBuilder {
+            capabilities: Capabilities {
+                granted: [Capability::Core].iter().cloned().collect(),
+                denied: HashSet::new(),
+            },
+            truthiness: Truthiness::default(),
+            limits: Limits::default(),
+        }
+    }
+
+    /// grant a group of builtins
+    pub fn allow(mut self, group: Capability) -> Self {
+        self.capabilities.granted.insert(group);
+        self
+    }
+
+    /// grant every group of builtins
+    pub fn allow_all(mut self) -> Self {
+        self.capabilities.granted.extend(Capability::ALL);
+        self
+    }
+
+    /// take a group of builtins away again
+    pub fn revoke(mut self, group: Capability) -> Self {
+        self.capabilities.granted.remove(&group);
+        self
+    }
+
+    /// forbid one builtin, even if its group is granted
+    pub fn deny(mut self, name: &str) -> Self {
+        self.capabilities.denied.insert(name.to_owned());
+        self
+    }
+
+    /// choose which values count as false in conditions
+    pub fn truthiness(mut self, truthiness: Truthiness) -> Self {
+        self.truthiness = truthiness;
+        self
+    }
+
+    /// limit how much work each call to `run` may do
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn build(self) -> Interpreter {
+        let mut env = Env::new(None);
+        env.truthiness = self.truthiness;
+        env.capabilities = Shared::new(self.capabilities);
+        env.budget.set_limits(self.limits);
+
+        Interpreter { env: Shared::new(Lock::new(env)) }
+    }
+}
+
+impl Default for InterpreterBuilder {
+    fn default() -> Self {
+        InterpreterBuilder::new()
+    }
+}
@@ -0,0 +1,635 @@
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use serde::ser::{self, Serialize, SerializeMap};
+use serde::{Deserialize, Deserializer, Serializer};
+use std::fmt;
+
+use crate::values::Value::{self, *};
+
+// with the `serde` feature, a `Value` can be written to and read from any
+// self-describing serde format. most values map onto the serde data model
+// directly: numbers are numbers, strings are strings, lists are sequences and
+// nil is unit. the ones with no equivalent are written as a map with a single
+// tagged key, e.g. `{"$symbol": "foo"}` or `{"$rational": "1/3"}`. procedures
+// can't be serialized at all.
+//
+// `to_value` and `from_value` go the other way, between lisprs values and any
+// rust type that implements `Serialize` or `Deserialize`. structs and maps
+// become association lists, `((field value) ...)`, with the field names as
+// symbols; enum variants become symbols, or `(variant value)` if they hold
+// anything.
+
+const SYMBOL_TAG: &str = "$symbol";
+const BIGNUM_TAG: &str = "$bignum";
+const RATIONAL_TAG: &str = "$rational";
+
+/// an error converting between rust data and lisprs values
+#[derive(Debug)]
+pub struct ConvertError(String);
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+impl ser::Error for ConvertError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ConvertError(msg.to_string())
+    }
+}
+
+impl de::Error for ConvertError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ConvertError(msg.to_string())
+    }
+}
+
+/// turn any serializable rust value into a lisprs value
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, ConvertError> {
+    value.serialize(ValueSerializer)
+}
+
+/// turn a lisprs value into any deserializable rust type
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, ConvertError> {
+    T::deserialize(value)
+}
+
+// {{{ Value <-> serde formats
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Symbol(s)   => tagged(serializer, SYMBOL_TAG, s),
+            Str(s)      => serializer.serialize_str(s),
+            Integer(n)  => serializer.serialize_i64(*n),
+            Bignum(n)   => tagged(serializer, BIGNUM_TAG, &n.to_string()),
+            Rational(n) => tagged(serializer, RATIONAL_TAG, &n.to_string()),
+            Float(n)    => serializer.serialize_f64(*n),
+            Bool(b)     => serializer.serialize_bool(*b),
+            List(list)  => serializer.collect_seq(list),
+            Nil         => serializer.serialize_unit(),
+            Proc(_) | Native(_) => Err(ser::Error::custom("procedures can't be serialized")),
+            Port(_) | Eof => Err(ser::Error::custom("ports can't be serialized")),
+        }
+    }
+}
+
+fn tagged<S: Serializer>(serializer: S, tag: &str, value: &str) -> Result<S::Ok, S::Error> {
+    let mut map = serializer.serialize_map(Some(1))?;
+    map.serialize_entry(tag, value)?;
+    map.end()
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a lisprs value")
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<Value, E> {
+        Ok(Bool(b))
+    }
+
+    fn visit_i64<E>(self, n: i64) -> Result<Value, E> {
+        Ok(Integer(n))
+    }
+
+    fn visit_u64<E>(self, n: u64) -> Result<Value, E> {
+        Ok(Value::from_bigint(BigInt::from(n)))
+    }
+
+    fn visit_i128<E>(self, n: i128) -> Result<Value, E> {
+        Ok(Value::from_bigint(BigInt::from(n)))
+    }
+
+    fn visit_u128<E>(self, n: u128) -> Result<Value, E> {
+        Ok(Value::from_bigint(BigInt::from(n)))
+    }
+
+    fn visit_f64<E>(self, n: f64) -> Result<Value, E> {
+        Ok(Float(n))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Value, E> {
+        Ok(Str(s.to_owned()))
+    }
+
+    fn visit_string<E>(self, s: String) -> Result<Value, E> {
+        Ok(Str(s))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Nil)
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Nil)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut list = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(item) = seq.next_element()? {
+            list.push(item);
+        }
+        Ok(List(list))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut pairs = Vec::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some((key, value)) = map.next_entry::<Value, Value>()? {
+            pairs.push((key, value));
+        }
+
+        // a single tagged entry is one of the values with no serde equivalent
+        if pairs.len() == 1 {
+            if let (Str(tag), Str(value)) = &pairs[0] {
+                let invalid = || de::Error::custom(format!("invalid {} '{}'", tag, value));
+                match tag.as_str() {
+                    SYMBOL_TAG   => return Ok(Symbol(value.clone())),
+                    BIGNUM_TAG   => return value.parse().map(Value::from_bigint).map_err(|_| invalid()),
+                    RATIONAL_TAG => return value.parse().map(Value::from_rational).map_err(|_| invalid()),
+                    _ => (),
+                }
+            }
+        }
+
+        Ok(List(pairs.into_iter().map(|(key, value)| List(vec![key, value])).collect()))
+    }
+}
+// }}}
+
+// {{{ rust data -> Value
+struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = ConvertError;
+
+    type SerializeSeq = ListSerializer;
+    type SerializeTuple = ListSerializer;
+    type SerializeTupleStruct = ListSerializer;
+    type SerializeTupleVariant = VariantSerializer<ListSerializer>;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = VariantSerializer<MapSerializer>;
+
+    fn serialize_bool(self, b: bool) -> Result<Value, ConvertError> {
+        Ok(Bool(b))
+    }
+
+    fn serialize_i8(self, n: i8) -> Result<Value, ConvertError> {
+        Ok(Integer(n.into()))
+    }
+
+    fn serialize_i16(self, n: i16) -> Result<Value, ConvertError> {
+        Ok(Integer(n.into()))
+    }
+
+    fn serialize_i32(self, n: i32) -> Result<Value, ConvertError> {
+        Ok(Integer(n.into()))
+    }
+
+    fn serialize_i64(self, n: i64) -> Result<Value, ConvertError> {
+        Ok(Integer(n))
+    }
+
+    fn serialize_i128(self, n: i128) -> Result<Value, ConvertError> {
+        Ok(Value::from_bigint(BigInt::from(n)))
+    }
+
+    fn serialize_u8(self, n: u8) -> Result<Value, ConvertError> {
+        Ok(Integer(n.into()))
+    }
+
+    fn serialize_u16(self, n: u16) -> Result<Value, ConvertError> {
+        Ok(Integer(n.into()))
+    }
+
+    fn serialize_u32(self, n: u32) -> Result<Value, ConvertError> {
+        Ok(Integer(n.into()))
+    }
+
+    fn serialize_u64(self, n: u64) -> Result<Value, ConvertError> {
+        Ok(Value::from_bigint(BigInt::from(n)))
+    }
+
+    fn serialize_u128(self, n: u128) -> Result<Value, ConvertError> {
+        Ok(Value::from_bigint(BigInt::from(n)))
+    }
+
+    fn serialize_f32(self, n: f32) -> Result<Value, ConvertError> {
+        Ok(Float(n.into()))
+    }
+
+    fn serialize_f64(self, n: f64) -> Result<Value, ConvertError> {
+        Ok(Float(n))
+    }
+
+    fn serialize_char(self, c: char) -> Result<Value, ConvertError> {
+        Ok(Str(c.to_string()))
+    }
+
+    fn serialize_str(self, s: &str) -> Result<Value, ConvertError> {
+        Ok(Str(s.to_owned()))
+    }
+
+    fn serialize_bytes(self, bytes: &[u8]) -> Result<Value, ConvertError> {
+        Ok(List(bytes.iter().map(|&b| Integer(b.into())).collect()))
+    }
+
+    fn serialize_none(self) -> Result<Value, ConvertError> {
+        Ok(Nil)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, ConvertError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, ConvertError> {
+        Ok(Nil)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, ConvertError> {
+        Ok(Nil)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str)
+        -> Result<Value, ConvertError>
+    {
+        Ok(Symbol(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T)
+        -> Result<Value, ConvertError>
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, ConvertError> {
+        Ok(List(vec![Symbol(variant.to_owned()), to_value(value)?]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<ListSerializer, ConvertError> {
+        Ok(ListSerializer(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<ListSerializer, ConvertError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<ListSerializer, ConvertError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, variant: &'static str, len: usize)
+        -> Result<Self::SerializeTupleVariant, ConvertError>
+    {
+        Ok(VariantSerializer(variant, self.serialize_seq(Some(len))?))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer, ConvertError> {
+        Ok(MapSerializer { pairs: Vec::with_capacity(len.unwrap_or(0)), key: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, ConvertError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, variant: &'static str, len: usize)
+        -> Result<Self::SerializeStructVariant, ConvertError>
+    {
+        Ok(VariantSerializer(variant, self.serialize_map(Some(len))?))
+    }
+}
+
+struct ListSerializer(Vec<Value>);
+
+impl ser::SerializeSeq for ListSerializer {
+    type Ok = Value;
+    type Error = ConvertError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConvertError> {
+        self.0.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, ConvertError> {
+        Ok(List(self.0))
+    }
+}
+
+impl ser::SerializeTuple for ListSerializer {
+    type Ok = Value;
+    type Error = ConvertError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConvertError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, ConvertError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for ListSerializer {
+    type Ok = Value;
+    type Error = ConvertError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConvertError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, ConvertError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct MapSerializer {
+    pairs: Vec<Value>,
+    key: Option<Value>,
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Value;
+    type Error = ConvertError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), ConvertError> {
+        self.key = Some(to_value(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConvertError> {
+        let key = self.key.take().ok_or_else(|| ConvertError("map value without a key".to_owned()))?;
+        self.pairs.push(List(vec![key, to_value(value)?]));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, ConvertError> {
+        Ok(List(self.pairs))
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Value;
+    type Error = ConvertError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, field: &'static str, value: &T)
+        -> Result<(), ConvertError>
+    {
+        self.pairs.push(List(vec![Symbol(field.to_owned()), to_value(value)?]));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, ConvertError> {
+        Ok(List(self.pairs))
+    }
+}
+
+/// an enum variant holding several values, which becomes `(variant values)`
+struct VariantSerializer<S>(&'static str, S);
+
+impl ser::SerializeTupleVariant for VariantSerializer<ListSerializer> {
+    type Ok = Value;
+    type Error = ConvertError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), ConvertError> {
+        ser::SerializeSeq::serialize_element(&mut self.1, value)
+    }
+
+    fn end(self) -> Result<Value, ConvertError> {
+        Ok(List(vec![Symbol(self.0.to_owned()), ser::SerializeSeq::end(self.1)?]))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<MapSerializer> {
+    type Ok = Value;
+    type Error = ConvertError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, field: &'static str, value: &T)
+        -> Result<(), ConvertError>
+    {
+        ser::SerializeStruct::serialize_field(&mut self.1, field, value)
+    }
+
+    fn end(self) -> Result<Value, ConvertError> {
+        Ok(List(vec![Symbol(self.0.to_owned()), ser::SerializeStruct::end(self.1)?]))
+    }
+}
+// }}}
+
+// {{{ Value -> rust data
+// a rust type that asks for a string can have a symbol, and one that asks for
+// a float can have a rational. anything that takes whatever it's given, like
+// a `Value`, gets them tagged the way they're serialized instead, so that
+// nothing is lost.
+impl<'de> Deserializer<'de> for Value {
+    type Error = ConvertError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConvertError> {
+        match self {
+            Symbol(s)   => visitor.visit_map(tagged_map(SYMBOL_TAG, s)),
+            Str(s)      => visitor.visit_string(s),
+            Integer(n)  => visitor.visit_i64(n),
+            Bignum(n)   => match (n.to_i128(), n.to_u128()) {
+                (Some(n), _) => visitor.visit_i128(n),
+                (_, Some(n)) => visitor.visit_u128(n),
+                _ => Err(de::Error::custom(format!("{} is too big", n))),
+            },
+            Rational(n) => visitor.visit_map(tagged_map(RATIONAL_TAG, n.to_string())),
+            Float(n)    => visitor.visit_f64(n),
+            Bool(b)     => visitor.visit_bool(b),
+            List(list)  => visitor.visit_seq(list.into_deserializer()),
+            Nil         => visitor.visit_unit(),
+            Proc(_) | Native(_) => Err(de::Error::custom("procedures can't be deserialized")),
+            Port(_) | Eof => Err(de::Error::custom("ports can't be deserialized")),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConvertError> {
+        match self {
+            Symbol(s) => visitor.visit_string(s),
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConvertError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConvertError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConvertError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConvertError> {
+        match self {
+            Rational(n) => visitor.visit_f64(Rational(n).to_f64()),
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConvertError> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConvertError> {
+        match self {
+            Nil => visitor.visit_none(),
+            value => visitor.visit_some(value),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConvertError> {
+        match self {
+            Nil => visitor.visit_unit(),
+            List(ref list) if list.is_empty() => visitor.visit_unit(),
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V)
+        -> Result<V::Value, ConvertError>
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V)
+        -> Result<V::Value, ConvertError>
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ConvertError> {
+        let pairs = match self {
+            List(list) => list.into_iter()
+                .map(|pair| match pair {
+                    List(mut pair) if pair.len() == 2 => {
+                        let value = pair.pop().unwrap();
+                        Ok((pair.pop().unwrap(), value))
+                    }
+                    other => Err(de::Error::custom(format!(
+                        "expected a (key value) pair, got {}", other.serialize()
+                    ))),
+                })
+                .collect::<Result<Vec<_>, ConvertError>>()?,
+            Nil => vec![],
+            other => return other.deserialize_any(visitor),
+        };
+
+        visitor.visit_map(de::value::MapDeserializer::new(pairs.into_iter()))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ConvertError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ConvertError> {
+        match self {
+            Symbol(variant) | Str(variant) => visitor.visit_enum(variant.into_deserializer()),
+            List(mut list) => match (list.len(), list.pop(), list.pop()) {
+                (2, Some(value), Some(Symbol(variant))) | (2, Some(value), Some(Str(variant))) => {
+                    visitor.visit_enum(EnumDeserializer { variant, value })
+                }
+                _ => Err(de::Error::custom("expected an enum variant, like (variant value)")),
+            },
+            other => Err(de::Error::custom(format!(
+                "expected an enum variant, got {}", other.serialize()
+            ))),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128
+        bytes byte_buf seq tuple tuple_struct ignored_any
+    }
+}
+
+/// a value with no serde equivalent, as the single-entry map it's serialized as
+fn tagged_map(tag: &'static str, value: String)
+    -> de::value::MapDeserializer<'static, std::iter::Once<(&'static str, String)>, ConvertError>
+{
+    de::value::MapDeserializer::new(std::iter::once((tag, value)))
+}
+
+impl<'de> IntoDeserializer<'de, ConvertError> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
+
+/// an enum variant with a value, read from `(variant value)`
+struct EnumDeserializer {
+    variant: String,
+    value: Value,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+    type Error = ConvertError;
+    type Variant = Value;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Value), ConvertError> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, self.value))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Value {
+    type Error = ConvertError;
+
+    fn unit_variant(self) -> Result<(), ConvertError> {
+        Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, ConvertError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, ConvertError> {
+        self.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V)
+        -> Result<V::Value, ConvertError>
+    {
+        self.deserialize_map(visitor)
+    }
+}
+// }}}
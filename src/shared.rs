@@ -0,0 +1,56 @@
+// the pointers that Envs, procedures and native functions are shared
+// through. normally they're `Rc` and `RefCell`, but with the `sync` feature
+// they're `Arc` and `RwLock` instead, so that an interpreter, and everything
+// it defines, can be sent to and shared between threads.
+//
+// `Lock` has the same `borrow`/`borrow_mut` as `RefCell` either way, so the
+// code that uses it doesn't have to care which one it gets.
+
+#[cfg(not(feature = "sync"))]
+pub use std::cell::RefCell as Lock;
+#[cfg(not(feature = "sync"))]
+pub use std::rc::Rc as Shared;
+
+#[cfg(feature = "sync")]
+pub use self::sync::Lock;
+#[cfg(feature = "sync")]
+pub use std::sync::Arc as Shared;
+
+/// `Send + Sync` with the `sync` feature, and nothing at all without it.
+/// rust functions handed to the interpreter have to be this.
+#[cfg(not(feature = "sync"))]
+pub trait MaybeSync {}
+#[cfg(not(feature = "sync"))]
+impl<T: ?Sized> MaybeSync for T {}
+
+/// `Send + Sync` with the `sync` feature, and nothing at all without it.
+/// rust functions handed to the interpreter have to be this.
+#[cfg(feature = "sync")]
+pub trait MaybeSync: Send + Sync {}
+#[cfg(feature = "sync")]
+impl<T: ?Sized + Send + Sync> MaybeSync for T {}
+
+#[cfg(feature = "sync")]
+mod sync {
+    use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+    /// a `RwLock` that looks like a `RefCell`
+    #[derive(Debug, Default)]
+    pub struct Lock<T>(RwLock<T>);
+
+    impl<T> Lock<T> {
+        pub fn new(value: T) -> Lock<T> {
+            Lock(RwLock::new(value))
+        }
+
+        // a panic while holding the lock leaves the value as it was, which
+        // is no worse than a RefCell after the same panic
+        pub fn borrow(&self) -> RwLockReadGuard<'_, T> {
+            self.0.read().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        pub fn borrow_mut(&self) -> RwLockWriteGuard<'_, T> {
+            self.0.write().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+}
@@ -1,14 +1,17 @@
 use failure::Error;
-use itertools::join;
-use std::cell::RefCell;
+use num_bigint::BigInt;
+use num_rational::BigRational;
 use std::cmp::Ordering;
 use std::fmt;
-use std::rc::Rc;
 
 use crate::env::*;
 use crate::eval;
 use crate::errors::*;
 use crate::parser::{self, Token};
+use crate::ports::Port;
+use crate::printer::{self, Style};
+use crate::shared::{Lock, MaybeSync, Shared};
+use crate::Interpreter;
 
 /// representation of lisprs' data types
 #[derive(Debug, Clone)]
@@ -16,94 +19,111 @@ pub enum Value {
     Symbol(String),
     Str(String),
     Integer(i64),
+    Bignum(BigInt),
+    Rational(BigRational),
     Float(f64),
     Bool(bool),
     List(Vec<Value>),
     Proc(Box<LispProc>),
+    Native(NativeFn),
+    Port(Port),
+    /// what reading past the end of a port gives
+    Eof,
     Nil,
 }
 
 use self::Value::*;
 
+/// which values count as false when a `Value` is used as a condition
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Truthiness {
+    /// only #f is false; everything else, including nil, empty lists
+    /// and 0, is true. this is the default.
+    #[default]
+    Scheme,
+    /// #f, nil, the empty list, 0 and 0.0 are false, and everything else
+    /// is true, like lisprs used to do it.
+    Legacy,
+}
+
 impl Value {
-    /// parse a string into a structured s-expression
+    /// parse a string into a structured s-expression. it has to hold just
+    /// one; see `parse_all` for code with several.
     pub fn new(s: String) -> Result<Self, Error> {
-        let mut tokens = parser::tokenize(&s);
+        let mut values = Value::parse_all(&s)?;
+        if values.len() > 1 {
+            return Err(ParseError::ErroneousToken(values[1].serialize()).into());
+        }
+        Ok(values.remove(0))
+    }
+
+    /// parse a string into every s-expression in it, in order
+    pub fn parse_all(s: &str) -> Result<Vec<Self>, Error> {
+        let mut tokens = parser::tokenize(s);
         let left_parens = tokens.iter().filter(|&t| t == &Token::LeftParen).count();
         let right_parens = tokens.iter().filter(|&t| t == &Token::RightParen).count();
 
         if tokens.is_empty() {
-            Err(ParseError::Empty.into())
-        } else if left_parens == right_parens {
-            Value::from_tokens(&mut tokens)
-        } else {
-            Err(ParseError::MismatchedParens.into())
+            return Err(ParseError::Empty.into());
+        } else if left_parens != right_parens {
+            return Err(ParseError::MismatchedParens.into());
         }
+
+        let mut values = Vec::new();
+        while !tokens.is_empty() {
+            values.push(Value::from_tokens(&mut tokens)?);
+        }
+        Ok(values)
     }
 
-    /// represent a `Value` as a human-friendly string
+    /// represent a `Value` as a human-friendly string, the way `display`
+    /// prints it: strings without quotes, even inside lists
     pub fn to_string(&self) -> String {
-        match self {
-            Symbol(s)   => s.clone(),
-            Str(s)      => s.to_owned(),
-            Integer(n)  => n.to_string(),
-            Float(n)    => n.to_string(),
-            Bool(true)  => "#t".to_owned(),
-            Bool(false) => "#f".to_owned(),
-            Nil         => "nil".to_owned(),
-
-            List(list)  => format!("({})",
-                                   join(list.iter().map(|item| item.serialize()), " ")),
-
-            Proc(p)     => format!("(lambda ({}) {})",
-                                   join(p.params.iter(), " "),
-                                   p.body.to_string()),
-        }
+        printer::print(self, Style::Display)
     }
 
-    /// represent a `Value` as a slightly less human-friendly string for saving externally
+    /// represent a `Value` as a string that `read` gives back the same
+    /// value for, the way `write` prints it
     pub fn serialize(&self) -> String {
-        match self {
-            Symbol(s)   => s.clone(),
-            Str(s)      => format!("\"{}\"", s),
-            Integer(n)  => n.to_string(),
-            Float(n)    => n.to_string(),
-            Bool(true)  => "#t".to_owned(),
-            Bool(false) => "#f".to_owned(),
-            Nil         => "nil".to_owned(),
-
-            List(list)  => format!("({})",
-                                   join(list.iter().map(|item| item.serialize()), " ")),
-
-            Proc(p)     => format!("(lambda ({}) {})",
-                                   join(p.params.iter(), " "),
-                                   p.body.serialize()),
-        }
+        printer::print(self, Style::Write)
     }
 
-    /// make a bool out of a value. nil, empty list, and 0 are falsy.
-    pub fn to_bool(&self) -> bool {
-        match self {
-            Bool(b)    => *b,
-            Nil        => false,
-            List(l)    => l.is_empty(),
-            Integer(n) => *n != 0i64,
-            Float(n)   => *n != 0f64,
-            _ => true,
+    /// `serialize`, but with lists that would be wider than `width` columns
+    /// broken over several lines and indented, see `printer`
+    pub fn pretty(&self, width: usize) -> String {
+        printer::pretty(self, Style::Write, width)
+    }
+
+    /// make a bool out of a value, for `if`, `cond`, `not`, `and` and `or`.
+    /// see `Truthiness` for which values count as false.
+    pub fn to_bool(&self, truthiness: Truthiness) -> bool {
+        match (truthiness, self) {
+            (_, Bool(b))                     => *b,
+            (Truthiness::Scheme, _)          => true,
+            (Truthiness::Legacy, Nil)        => false,
+            (Truthiness::Legacy, List(l))    => !l.is_empty(),
+            (Truthiness::Legacy, Integer(n)) => *n != 0i64,
+            (Truthiness::Legacy, Float(n))   => *n != 0f64,
+            (Truthiness::Legacy, _)          => true,
         }
     }
 
     /// get the human-friendly type of a `Value`
     pub fn get_type(&self) -> String {
         match self {
-            Symbol(_)  => "Symbol",
-            Str(_)     => "Str",
-            Integer(_) => "Integer",
-            Float(_)   => "Float",
-            Bool(_)    => "Bool",
-            List(_)    => "List",
-            Proc(_)    => "Proc",
-            Nil        => "Nil",
+            Symbol(_)   => "Symbol",
+            Str(_)      => "Str",
+            Integer(_)  => "Integer",
+            Bignum(_)   => "Bignum",
+            Rational(_) => "Rational",
+            Float(_)    => "Float",
+            Bool(_)     => "Bool",
+            List(_)     => "List",
+            Proc(_)     => "Proc",
+            Native(_)   => "Native",
+            Port(_)     => "Port",
+            Eof         => "Eof",
+            Nil         => "Nil",
         }.to_owned()
     }
 }
@@ -118,12 +138,12 @@ impl PartialEq for Value {
     fn eq(&self, other: &Value) -> bool {
         match (self, other) {
             (Bool(a), Bool(b))       => a == b,
-            (Integer(a), Integer(b)) => a == b,
-            (Float(a), Float(b))     => a == b,
-            (Integer(a), Float(b))   => &(*a as f64) == b,
-            (Float(a), Integer(b))   => a == &(*b as f64),
+            (a, b) if a.is_number() && b.is_number() => a.num_cmp(b) == Some(Ordering::Equal),
             (Symbol(a), Symbol(b))   => a == b,
             (Str(a), Str(b))         => a == b,
+            (List(a), List(b))       => a == b,
+            (Port(a), Port(b))       => a.same(b),
+            (Eof, Eof)               => true,
             (Nil, Nil)               => true,
             _ => false, // values of different types are not equivalent
         }
@@ -132,12 +152,39 @@ impl PartialEq for Value {
 
 impl PartialOrd for Value {
     fn partial_cmp(&self, other: &Value) -> Option<Ordering> {
-        match (self, other) {
-            (Integer(a), Integer(b)) => a.partial_cmp(b),
-            (Float(a), Float(b))     => a.partial_cmp(b),
-            (Integer(a), Float(b))   => (*a as f64).partial_cmp(b),
-            (Float(a), Integer(b))   => a.partial_cmp(&(*b as f64)),
-            _ => None
+        if self.is_number() && other.is_number() {
+            self.num_cmp(other)
+        } else {
+            None
+        }
+    }
+}
+
+impl Value {
+    /// can this value be called, like `(f x)`?
+    pub fn is_callable(&self) -> bool {
+        matches!(self, Proc(_) | Native(_))
+    }
+
+    /// call a procedure or native function with some already-evaluated
+    /// arguments, under the limits, truthiness and ports of `interpreter`,
+    /// even if the procedure was defined somewhere else, like a `FrozenEnv`
+    /// that several interpreters share
+    pub fn call(&self, interpreter: &Interpreter, args: Vec<Value>) -> Result<Value, Error> {
+        interpreter.env.borrow().budget.start();
+        self.call_from(args, &interpreter.env)
+    }
+
+    /// call a procedure or native function from inside an Env, under its
+    /// settings, see `LispProc::call_from`
+    pub fn call_from(&self, args: Vec<Value>, caller: &EnvRef) -> Result<Value, Error> {
+        match self {
+            Proc(p)   => p.call_from("<anonymous procedure>".to_owned(), args, caller),
+            Native(f) => f.call(args),
+            _ => Err(RunError::UncallableValue {
+                name: self.to_string(),
+                typename: self.get_type(),
+            }.into()),
         }
     }
 }
@@ -153,7 +200,14 @@ pub struct LispProc {
 
 impl LispProc {
     /// run a LispProc with some arguments
-    pub fn call(&self, name: String, mut args: Vec<Value>) -> Result<Value, Error> {
+    pub fn call(&self, name: String, args: Vec<Value>) -> Result<Value, Error> {
+        self.call_from(name, args, &self.env)
+    }
+
+    /// run a LispProc with some arguments, under the settings of the Env it's
+    /// called from rather than the one it was defined in. they're only
+    /// different for a procedure from a `FrozenEnv`.
+    pub fn call_from(&self, name: String, mut args: Vec<Value>, caller: &EnvRef) -> Result<Value, Error> {
         // let mut args = eval::eval_list(args, self.env.clone())?;
         // log::debug(format!("calling {} with args: {:?}", name, args));
 
@@ -166,20 +220,57 @@ impl LispProc {
         }
 
         let mut local_env = Env::new(Some(self.env.clone()));
+        local_env.inherit_settings(&caller.borrow());
 
         let mut i = 0;
         while i < self.params.len() {
             if self.params[i] == "." {
                 i += 1;
-                local_env.define(&self.params[i], Value::List(args));
+                local_env.define(&self.params[i], Value::List(args))?;
                 break;
             }
 
-            local_env.define(&self.params[i], args.remove(0));
+            local_env.define(&self.params[i], args.remove(0))?;
             i += 1;
         }
 
-        let local_env_ref: EnvRef = Rc::new(RefCell::new(local_env));
+        let local_env_ref: EnvRef = Shared::new(Lock::new(local_env));
         eval::eval(self.body.clone(), local_env_ref)
     }
 }
+
+/// a function written in rust, registered with `Interpreter::register_fn`.
+/// it gets its arguments already evaluated, like a `LispProc`.
+#[derive(Clone)]
+pub struct NativeFn {
+    pub name: String,
+    func: NativeFnPtr,
+}
+
+#[cfg(not(feature = "sync"))]
+type NativeFnPtr = Shared<dyn Fn(Vec<Value>) -> Result<Value, Error>>;
+#[cfg(feature = "sync")]
+type NativeFnPtr = Shared<dyn Fn(Vec<Value>) -> Result<Value, Error> + Send + Sync>;
+
+impl NativeFn {
+    pub fn new<F>(name: &str, func: F) -> NativeFn
+    where
+        F: Fn(Vec<Value>) -> Result<Value, Error> + MaybeSync + 'static,
+    {
+        NativeFn {
+            name: name.to_owned(),
+            func: Shared::new(func),
+        }
+    }
+
+    /// run the function with some arguments
+    pub fn call(&self, args: Vec<Value>) -> Result<Value, Error> {
+        (self.func)(args)
+    }
+}
+
+impl fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NativeFn({})", self.name)
+    }
+}